@@ -1,147 +1,72 @@
 use crate::error::Error;
 use crate::model::HttpMethod;
 use crate::model::persistent::HttpStubResponse;
-use actix_http::header::HeaderMap;
-use actix_web::{get, head, post, put, delete, options, patch, HttpResponse, HttpRequest, Responder, ResponseError, Result};
-use actix_web::web::{Bytes, Data, Query};
-use exec::ExecHandler;
+use actix_files::NamedFile;
+use actix_http::header::{self, HeaderMap, HeaderName, HeaderValue};
+use actix_web::{HttpResponse, HttpRequest, ResponseError, Result};
+use actix_web::web::{Bytes, Data};
+use exec::{ExecHandler, ExecResponse};
 use http::StatusCode;
-use model::RequestBody;
-use serde_json::Value;
+use model::{urlencoded_to_json_value, RequestBody};
+use proxy::{UpstreamRequest, UpstreamResponse};
 use std::collections::HashMap;
 use tokio::time::sleep;
 
+pub mod admin;
+pub mod callback;
 pub mod exec;
+pub mod journal;
 pub mod model;
+pub mod multipart;
+pub mod proxy;
+pub mod recorder;
 pub mod resolver;
+pub mod verify;
 
-#[get("/api/kolibri/exec/{path:.*}")]
-pub async fn exec_get(req: HttpRequest, body_bytes: Bytes, exec_handler: Data<ExecHandler>) -> Result<impl Responder> {
-    let resp = exec_handler.get_ref().exec(
-        HttpMethod::Get, 
-        req.path().strip_prefix("/api/kolibri/exec").unwrap_or("").to_string(), 
-        headermap_to_hashmap(req.headers()), 
-        query_string_to_json_value(req.query_string())?, 
-        bytes_to_request_body(body_bytes)?
-    ).await?;
-
-    if let Some(delay) = resp.get_delay() {
-        sleep(delay.clone()).await;
-    }
-
-    Ok(response_to_responder(resp))
-}
-
-#[head("/api/kolibri/exec/{path:.*}")]
-pub async fn exec_head(req: HttpRequest, body_bytes: Bytes, exec_handler: Data<ExecHandler>) -> Result<impl Responder> {
-    let resp = exec_handler.get_ref().exec(
-        HttpMethod::Head, 
-        req.path().strip_prefix("/api/kolibri/exec").unwrap_or("").to_string(), 
-        headermap_to_hashmap(req.headers()), 
-        query_string_to_json_value(req.query_string())?, 
-        bytes_to_request_body(body_bytes)?
-    ).await?;
-
-    if let Some(delay) = resp.get_delay() {
-        sleep(delay.clone()).await;
-    }
+pub async fn exec(req: HttpRequest, body_bytes: Bytes, exec_handler: Data<ExecHandler>) -> Result<HttpResponse> {
+    let Some(path) = exec_handler.strip_prefix(req.path()) else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
 
-    Ok(response_to_responder(resp))
-}
+    let upstream = UpstreamRequest {
+        method: req.method().clone(),
+        headers: req.headers().clone(),
+        query_string: req.query_string().to_string(),
+        body: body_bytes.clone()
+    };
 
-#[post("/api/kolibri/exec/{path:.*}")]
-pub async fn exec_post(req: HttpRequest, body_bytes: Bytes, exec_handler: Data<ExecHandler>) -> Result<impl Responder> {
     let resp = exec_handler.get_ref().exec(
-        HttpMethod::Post, 
-        req.path().strip_prefix("/api/kolibri/exec").unwrap_or("").to_string(), 
-        headermap_to_hashmap(req.headers()), 
-        query_string_to_json_value(req.query_string())?,
-        bytes_to_request_body(body_bytes)?
+        HttpMethod::from(req.method()),
+        path.to_string(),
+        headermap_to_hashmap(req.headers()),
+        urlencoded_to_json_value(req.query_string())?,
+        bytes_to_request_body(body_bytes, req.headers().get(header::CONTENT_TYPE).and_then(|ct| ct.to_str().ok()))?,
+        upstream
     ).await?;
 
-    if let Some(delay) = resp.get_delay() {
-        sleep(delay.clone()).await;
-    }
-
-    Ok(response_to_responder(resp))
-}
-
-#[put("/api/kolibri/exec/{path:.*}")]
-pub async fn exec_put(req: HttpRequest, body_bytes: Bytes, exec_handler: Data<ExecHandler>) -> Result<impl Responder> {
-    let resp = exec_handler.get_ref().exec(
-        HttpMethod::Put, 
-        req.path().strip_prefix("/api/kolibri/exec").unwrap_or("").to_string(), 
-        headermap_to_hashmap(req.headers()), 
-        query_string_to_json_value(req.query_string())?,
-        bytes_to_request_body(body_bytes)?
-    ).await?;
-
-    if let Some(delay) = resp.get_delay() {
-        sleep(delay.clone()).await;
-    }
-
-    Ok(response_to_responder(resp))
-}
-
-#[delete("/api/kolibri/exec/{path:.*}")]
-pub async fn exec_delete(req: HttpRequest, body_bytes: Bytes, exec_handler: Data<ExecHandler>) -> Result<impl Responder> {
-    let resp = exec_handler.get_ref().exec(
-        HttpMethod::Delete, 
-        req.path().strip_prefix("/api/kolibri/exec").unwrap_or("").to_string(), 
-        headermap_to_hashmap(req.headers()), 
-        query_string_to_json_value(req.query_string())?, 
-        bytes_to_request_body(body_bytes)?
-    ).await?;
-
-    if let Some(delay) = resp.get_delay() {
-        sleep(delay.clone()).await;
-    }
-
-    Ok(response_to_responder(resp))
-}
-
-#[options("/api/kolibri/exec/{path:.*}")]
-pub async fn exec_options(req: HttpRequest, body_bytes: Bytes, exec_handler: Data<ExecHandler>) -> Result<impl Responder> {
-    let resp = exec_handler.get_ref().exec(
-        HttpMethod::Options, 
-        req.path().strip_prefix("/api/kolibri/exec").unwrap_or("").to_string(), 
-        headermap_to_hashmap(req.headers()), 
-        query_string_to_json_value(req.query_string())?, 
-        bytes_to_request_body(body_bytes)?
-    ).await?;
-
-    if let Some(delay) = resp.get_delay() {
-        sleep(delay.clone()).await;
-    }
-
-    Ok(response_to_responder(resp))
-}
-
-#[patch("/api/kolibri/exec/{path:.*}")]
-pub async fn exec_patch(req: HttpRequest, body_bytes: Bytes, exec_handler: Data<ExecHandler>) -> Result<impl Responder> {
-    let resp = exec_handler.get_ref().exec(
-        HttpMethod::Patch, 
-        req.path().strip_prefix("/api/kolibri/exec").unwrap_or("").to_string(), 
-        headermap_to_hashmap(req.headers()), 
-        query_string_to_json_value(req.query_string())?,
-        bytes_to_request_body(body_bytes)?
-    ).await?;
+    match resp {
+        ExecResponse::Stubbed(resp) => {
+            if let Some(delay) = resp.get_delay() {
+                sleep(*delay).await;
+            }
 
-    if let Some(delay) = resp.get_delay() {
-        sleep(delay.clone()).await;
+            match resp {
+                HttpStubResponse::FileResponse { code, headers, path, .. } => file_to_responder(&req, code.as_u16(), headers, &path).await,
+                resp => Ok(response_to_responder(resp))
+            }
+        },
+        ExecResponse::Proxied(resp) => Ok(upstream_to_responder(resp))
     }
-
-    Ok(response_to_responder(resp))
 }
 
 // ---- private stuff ----
 
 impl ResponseError for Error { }
 
-fn response_to_responder(stub_response: HttpStubResponse) -> impl Responder {
+fn response_to_responder(stub_response: HttpStubResponse) -> HttpResponse {
     match stub_response {
         HttpStubResponse::RawResponse { code, headers, body, .. } => {
-            let mut builder = HttpResponse::build(StatusCode::from_u16(code).unwrap());
+            let mut builder = HttpResponse::build(StatusCode::from_u16(code.as_u16()).unwrap());
 
             for (key, value) in headers.into_iter() {
                 builder.append_header((key, value));
@@ -150,38 +75,120 @@ fn response_to_responder(stub_response: HttpStubResponse) -> impl Responder {
             builder.body(body)
         },
         HttpStubResponse::JsonResponse { code, headers, body, .. } => {
-            let mut builder = HttpResponse::build(StatusCode::from_u16(code).unwrap());
+            let mut builder = HttpResponse::build(StatusCode::from_u16(code.as_u16()).unwrap());
 
             for (key, value) in headers.into_iter() {
                 builder.append_header((key, value));
             }
 
             builder.body(body.to_string())
-        }
+        },
+        HttpStubResponse::XmlResponse { code, headers, body, .. } => {
+            let mut builder = HttpResponse::build(StatusCode::from_u16(code.as_u16()).unwrap());
+
+            if !headers.keys().any(|key| key.eq_ignore_ascii_case("content-type")) {
+                builder.content_type("application/xml");
+            }
+
+            for (key, value) in headers.into_iter() {
+                builder.append_header((key, value));
+            }
+
+            builder.body(body)
+        },
+        HttpStubResponse::BinaryResponse { code, headers, body, .. } => {
+            let mut builder = HttpResponse::build(StatusCode::from_u16(code.as_u16()).unwrap());
+
+            if !headers.keys().any(|key| key.eq_ignore_ascii_case("content-type")) {
+                builder.content_type("application/octet-stream");
+            }
+
+            for (key, value) in headers.into_iter() {
+                builder.append_header((key, value));
+            }
+
+            builder.body(body)
+        },
+        // files are served by file_to_responder
+        HttpStubResponse::FileResponse { .. } => HttpResponse::InternalServerError().finish(),
+        // ExecHandler resolves proxy responses, so this one never reached the upstream
+        HttpStubResponse::ProxyResponse { .. } => HttpResponse::BadGateway().finish()
+    }
+}
+
+/// `path` is expected to be resolved by ExecHandler already, content type is inferred from the extension unless set
+async fn file_to_responder(req: &HttpRequest, code: u16, headers: HashMap<String, String>, path: &str) -> Result<HttpResponse> {
+    let mut response = NamedFile::open_async(path).await?.into_response(req);
+
+    // conditional and range requests get their own codes
+    if response.status() == StatusCode::OK {
+        *response.status_mut() = StatusCode::from_u16(code).unwrap();
     }
+
+    for (key, value) in headers.into_iter() {
+        response.headers_mut().insert(
+            HeaderName::try_from(key).map_err(Error::from)?,
+            HeaderValue::try_from(value).map_err(Error::from)?
+        );
+    }
+
+    Ok(response)
+}
+
+fn upstream_to_responder(upstream_response: UpstreamResponse) -> HttpResponse {
+    let mut builder = HttpResponse::build(StatusCode::from_u16(upstream_response.code).unwrap());
+
+    for (key, value) in upstream_response.headers.into_iter() {
+        builder.append_header((key, value));
+    }
+
+    builder.body(upstream_response.body)
 }
 
 fn headermap_to_hashmap(headermap: &HeaderMap) -> HashMap<String, String> {
     headermap
         .into_iter()
-        .map(|(name, value)| (name.as_str().to_string(), format!("{:?}", value)))
+        .map(|(name, value)| (name.as_str().to_string(), String::from_utf8_lossy(value.as_bytes()).to_string()))
         .collect()
 }
 
-fn query_string_to_json_value(query_string: &str) -> Result<Value, Error> {
-    let params = Query::<Vec<(String, String)>>::from_query(query_string)
-        .map_err(|e| Error::from(e))?.0;
+fn bytes_to_request_body(body_bytes: Bytes, content_type: Option<&str>) -> Result<RequestBody, Error> {
+    if body_bytes.is_empty() {
+        return Ok(RequestBody::AbsentRequestBody);
+    }
+
+    let bytes_vec = body_bytes.to_vec();
 
-    Ok(Value::from_iter(params.into_iter().map(|(key, value)| (key, serde_json::from_str(value.as_str()).unwrap_or(Value::String(value)) ))))
+    // malformed multipart bodies are still available for raw matching
+    let parts = content_type.and_then(multipart::boundary_of)
+        .and_then(|boundary| multipart::parse(&boundary, &bytes_vec).ok());
+
+    Ok(match parts {
+        Some(parts) => RequestBody::MultipartRequestBody { raw_value: bytes_vec, parts, content_type: content_type.map(String::from) },
+        None => RequestBody::SimpleRequestBody {
+            value: String::from_utf8(bytes_vec.clone()).ok(),
+            raw_value: bytes_vec,
+            content_type: content_type.map(String::from)
+        }
+    })
 }
 
-fn bytes_to_request_body(body_bytes: Bytes) -> Result<RequestBody, Error> {
-    if body_bytes.is_empty() {
-        Ok(RequestBody::AbsentRequestBody)
-    } else {
-        let bytes_vec = body_bytes.to_vec();
-        String::from_utf8(bytes_vec.clone())
-            .map_err(|e| Error::from(e))
-            .map(|body_str| RequestBody::SimpleRequestBody { raw_value: bytes_vec, value: body_str })
+#[cfg(test)]
+mod api_tests {
+    use crate::api::headermap_to_hashmap;
+    use actix_http::header::{self, HeaderMap, HeaderValue};
+
+    #[test]
+    fn header_values_are_kept_as_is() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        let mut token = HeaderValue::from_static("Bearer abc");
+        token.set_sensitive(true);
+        headers.insert(header::AUTHORIZATION, token);
+
+        let converted = headermap_to_hashmap(&headers);
+
+        assert_eq!(converted["content-type"], "application/json");
+        assert_eq!(converted["authorization"], "Bearer abc");
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,163 @@
+use crate::api::journal::RequestJournal;
+use crate::api::resolver::{StubChange, StubResolver};
+use crate::api::verify::VerificationRequest;
+use crate::model::persistent::{HttpStub, State};
+use crate::predicate_dsl::json::JsonPredicate;
+use actix_web::{get, post, put, delete, HttpResponse, Responder};
+use actix_web::web::{Data, Json, Path};
+use chrono::Utc;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+#[get("/api/kolibri/stubs")]
+pub async fn list_stubs(stub_res: Data<StubResolver>) -> impl Responder {
+    HttpResponse::Ok().json(stub_res.stubs().await)
+}
+
+#[get("/api/kolibri/stubs/{id}")]
+pub async fn get_stub(id: Path<Uuid>, stub_res: Data<StubResolver>) -> impl Responder {
+    match stub_res.get_stub(&id).await {
+        Some(stub) => HttpResponse::Ok().json(stub),
+        None => not_found(format!("Can't find stub with id {}", id))
+    }
+}
+
+#[post("/api/kolibri/stubs")]
+pub async fn create_stub(stub: Json<HttpStub>, stub_res: Data<StubResolver>) -> impl Responder {
+    let mut stub = stub.into_inner();
+    stub.id = Uuid::new_v4();
+    stub.created = Utc::now();
+
+    if let Err(err) = stub.validate() {
+        return bad_request(err);
+    }
+
+    match stub_res.add_stub(stub.clone()).await {
+        Ok(()) => {
+            stub_res.seed_state(&stub).await;
+            HttpResponse::Created().json(stub)
+        },
+        Err(err) => internal_error(err)
+    }
+}
+
+#[put("/api/kolibri/stubs/{id}")]
+pub async fn update_stub(id: Path<Uuid>, stub: Json<HttpStub>, stub_res: Data<StubResolver>) -> impl Responder {
+    let mut stub = stub.into_inner();
+    stub.id = *id;
+
+    if let Err(err) = stub.validate() {
+        return bad_request(err);
+    }
+
+    match stub_res.replace_stub(stub).await {
+        Ok(StubChange::Applied) => HttpResponse::Ok().json(stub_res.get_stub(&id).await),
+        Ok(StubChange::NotFound) => not_found(format!("Can't find stub with id {}", id)),
+        Ok(StubChange::ReadOnly(label)) => read_only(label),
+        Err(err) => internal_error(err)
+    }
+}
+
+#[delete("/api/kolibri/stubs/{id}")]
+pub async fn delete_stub(id: Path<Uuid>, stub_res: Data<StubResolver>) -> impl Responder {
+    match stub_res.remove_stub(&id).await {
+        Ok(StubChange::Applied) => HttpResponse::NoContent().finish(),
+        Ok(StubChange::NotFound) => not_found(format!("Can't find stub with id {}", id)),
+        Ok(StubChange::ReadOnly(label)) => read_only(label),
+        Err(err) => internal_error(err)
+    }
+}
+
+#[get("/api/kolibri/states")]
+pub async fn list_states(stub_res: Data<StubResolver>) -> impl Responder {
+    HttpResponse::Ok().json(stub_res.states().await)
+}
+
+#[post("/api/kolibri/states/fetch")]
+pub async fn fetch_states(predicate: Json<JsonPredicate>, stub_res: Data<StubResolver>) -> impl Responder {
+    HttpResponse::Ok().json(stub_res.find_states(&predicate).await)
+}
+
+#[post("/api/kolibri/states")]
+pub async fn insert_state(data: Json<Value>, stub_res: Data<StubResolver>) -> impl Responder {
+    let data = data.into_inner();
+
+    if !data.is_object() {
+        return bad_request("State data should be a JSON object");
+    }
+
+    let state = State { data, ..State::fresh() };
+    let _guard = stub_res.lock_states().await;
+
+    match stub_res.upsert_state(state.clone()).await {
+        Ok(()) => HttpResponse::Created().json(state),
+        Err(err) => internal_error(err)
+    }
+}
+
+#[delete("/api/kolibri/states/{id}")]
+pub async fn delete_state(id: Path<Uuid>, stub_res: Data<StubResolver>) -> impl Responder {
+    let _guard = stub_res.lock_states().await;
+
+    match stub_res.remove_state(&id).await {
+        Ok(true) => HttpResponse::NoContent().finish(),
+        Ok(false) => not_found(format!("Can't find state with id {}", id)),
+        Err(err) => internal_error(err)
+    }
+}
+
+#[post("/api/kolibri/states/delete")]
+pub async fn delete_states(predicate: Json<JsonPredicate>, stub_res: Data<StubResolver>) -> impl Responder {
+    let _guard = stub_res.lock_states().await;
+
+    match stub_res.remove_states(&predicate).await {
+        Ok(deleted) => HttpResponse::Ok().json(json!({"deleted": deleted})),
+        Err(err) => internal_error(err)
+    }
+}
+
+#[get("/api/kolibri/journal")]
+pub async fn list_journal(journal: Data<RequestJournal>) -> impl Responder {
+    HttpResponse::Ok().json(journal.entries().await)
+}
+
+#[post("/api/kolibri/journal/find")]
+pub async fn find_in_journal(predicate: Json<JsonPredicate>, journal: Data<RequestJournal>) -> impl Responder {
+    HttpResponse::Ok().json(journal.find(&predicate).await)
+}
+
+#[delete("/api/kolibri/journal")]
+pub async fn clear_journal(journal: Data<RequestJournal>) -> impl Responder {
+    journal.clear().await;
+
+    HttpResponse::NoContent().finish()
+}
+
+#[post("/api/kolibri/verify")]
+pub async fn verify(request: Json<VerificationRequest>, journal: Data<RequestJournal>) -> impl Responder {
+    let result = request.verify(journal.entries().await);
+
+    if result.is_satisfied() {
+        HttpResponse::Ok().json(result)
+    } else {
+        HttpResponse::ExpectationFailed().json(result)
+    }
+}
+
+// ---- private stuff ----
+
+fn read_only(label: String) -> HttpResponse {
+    HttpResponse::Forbidden().json(json!({"error": format!("Stub {} is defined in a file and is read-only", label)}))
+}
+
+fn not_found(message: String) -> HttpResponse {
+    HttpResponse::NotFound().json(json!({"error": message}))
+}
+
+fn bad_request<E: std::fmt::Display>(err: E) -> HttpResponse {
+    HttpResponse::BadRequest().json(json!({"error": err.to_string()}))
+}
+
+fn internal_error<E: std::fmt::Display>(err: E) -> HttpResponse {
+    HttpResponse::InternalServerError().json(json!({"error": err.to_string()}))
+}
@@ -0,0 +1,187 @@
+use crate::api::resolver::StubResolver;
+use crate::error::Error;
+use crate::misc::{Renderable, Substitute};
+use crate::model::persistent::{Callback, CallbackRequest, CallbackResponseMode, State};
+use actix_web::http::Method;
+use actix_web::rt::spawn;
+use awc::Client;
+use json_value_merge::Merge;
+use log::{error, info};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::time::sleep;
+
+#[derive(Clone)]
+pub struct CallbackEngine {
+    stub_res: Arc<StubResolver>
+}
+
+impl CallbackEngine {
+    pub fn new(stub_res: Arc<StubResolver>) -> CallbackEngine {
+        CallbackEngine { stub_res }
+    }
+
+    /// Runs the callback chain in background, `data` is the templating context of the stub that triggered it
+    pub fn schedule(&self, callback: Callback, data: Value, state: Option<State>) {
+        let engine = self.clone();
+
+        spawn(async move {
+            if let Err(err) = engine.run(callback, data, state).await {
+                error!("Callback execution failed: {}", err);
+            }
+        });
+    }
+
+    async fn run(&self, callback: Callback, mut data: Value, mut state: Option<State>) -> Result<(), Error> {
+        let mut next = Some(callback);
+
+        while let Some(Callback::HttpCallback { request, response_mode, persist, callback, delay }) = next {
+            if let Some(delay) = delay {
+                sleep(delay).await;
+            }
+
+            let response_body = self.send(request, data.clone()).await?;
+
+            let response_json = match response_mode {
+                Some(CallbackResponseMode::Json) => Some(serde_json::from_slice::<Value>(&response_body).map_err(Error::from)?),
+                None => None
+            };
+
+            if let Some(mut persist_spec) = persist {
+                let _guard = self.stub_res.lock_states().await;
+
+                // the state may have been changed by other requests while the callback was running
+                if let Some(known) = &state {
+                    state = self.stub_res.get_state(&known.id).await.or(state);
+                }
+
+                persist_spec.fill(json!({
+                    "data": response_json,
+                    "state": state.as_ref().map(|s| s.data.clone())
+                }));
+
+                let mut current_state = state.unwrap_or(State::fresh());
+                current_state.data.merge(&persist_spec.render_json());
+
+                self.stub_res.upsert_state(current_state.clone()).await?;
+
+                data["state"] = current_state.data.clone();
+                state = Some(current_state);
+            }
+
+            next = callback.map(|cb| *cb);
+        }
+
+        Ok(())
+    }
+
+    async fn send(&self, request: CallbackRequest, data: Value) -> Result<Vec<u8>, Error> {
+        let (mut url, method, headers) = match &request {
+            CallbackRequest::CallbackRequestWithoutBody { url, method, headers } => (url.clone(), method, headers),
+            CallbackRequest::RawCallbackRequest { url, method, headers, .. } => (url.clone(), method, headers),
+            CallbackRequest::JsonCallbackRequest { url, method, headers, .. } => (url.clone(), method, headers)
+        };
+
+        url.substitute(data.clone());
+
+        let method = Method::from_bytes(method.as_str().as_bytes()).map_err(Error::from)?;
+        let mut client_request = Client::default().request(method.clone(), url.as_str());
+
+        for (key, value) in substitute_headers(headers, &data) {
+            client_request = client_request.insert_header((key, value));
+        }
+
+        info!("Executing callback [{}] {}", method, url);
+
+        let mut response = match request {
+            CallbackRequest::CallbackRequestWithoutBody { .. } =>
+                client_request.send().await,
+            CallbackRequest::RawCallbackRequest { mut body, .. } =>
+                client_request.send_body(body.substitute(data).clone()).await,
+            CallbackRequest::JsonCallbackRequest { mut body, .. } =>
+                client_request.send_json(body.substitute(data)).await
+        }.map_err(Error::from)?;
+
+        info!("Callback [{}] {} responded with {}", method, url, response.status());
+
+        response.body().await.map(|b| b.to_vec()).map_err(Error::from)
+    }
+}
+
+fn substitute_headers(headers: &HashMap<String, String>, data: &Value) -> Vec<(String, String)> {
+    headers.iter().map(|(key, value)| {
+        let mut value = value.clone();
+        value.substitute(data.clone());
+        (key.clone(), value)
+    }).collect()
+}
+
+#[cfg(test)]
+mod callback_tests {
+    use crate::api::callback::CallbackEngine;
+    use crate::model::HttpMethod;
+    use crate::model::persistent::{Callback, CallbackRequest, CallbackResponseMode, State};
+    use crate::test_utils::{listen, resolver};
+    use crate::utils::js::optic::JsonOptic;
+    use actix_web::{HttpRequest, HttpResponse};
+    use serde_json::{json, Value};
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    fn respond(req: &HttpRequest) -> HttpResponse {
+        match req.path() {
+            "/token" => HttpResponse::Ok().json(json!({"token": "abc"})),
+            _ => HttpResponse::Ok().json(json!({"done": true}))
+        }
+    }
+
+    fn callback(request: CallbackRequest, persist: (&str, Value), delay: Option<Duration>, next: Option<Callback>) -> Callback {
+        Callback::HttpCallback {
+            request,
+            response_mode: Some(CallbackResponseMode::Json),
+            persist: Some(HashMap::from([(JsonOptic::from_path(persist.0), persist.1)])),
+            callback: next.map(Box::new),
+            delay
+        }
+    }
+
+    #[actix_web::test]
+    async fn callback_chain_persists_responses() {
+        let (address, received) = listen(respond);
+
+        let resolver = Arc::new(resolver(vec![]));
+        let state = State { data: json!({"counter": 1}), ..State::fresh() };
+        resolver.upsert_state(state.clone()).await.unwrap();
+
+        let chain = callback(
+            CallbackRequest::JsonCallbackRequest {
+                url: format!("http://{}/token", address),
+                method: HttpMethod::Post,
+                headers: HashMap::new(),
+                body: json!({"id": "${id}"})
+            },
+            ("token", json!("${data.token}")),
+            Some(Duration::from_millis(100)),
+            Some(callback(
+                CallbackRequest::CallbackRequestWithoutBody {
+                    url: format!("http://{}/confirm/${{state.token}}", address),
+                    method: HttpMethod::Get,
+                    headers: HashMap::new()
+                },
+                ("done", json!("${data.done}")),
+                None,
+                None
+            ))
+        );
+
+        let started = Instant::now();
+        CallbackEngine::new(resolver.clone()).run(chain, json!({"id": 42, "state": state.data}), Some(state.clone())).await.unwrap();
+
+        assert!(started.elapsed() >= Duration::from_millis(100));
+        let received = received.lock().unwrap().iter().map(|r| format!("{} {}", r.path, r.body)).collect::<Vec<_>>();
+        assert_eq!(received, vec![r#"/token {"id":42}"#.to_string(), "/confirm/abc ".to_string()]);
+        assert_eq!(resolver.get_state(&state.id).await.unwrap().data, json!({"counter": 1, "token": "abc", "done": true}));
+    }
+}
@@ -1,33 +1,140 @@
+use crate::api::callback::CallbackEngine;
+use crate::api::journal::{JournalEntry, RequestJournal};
 use crate::api::model::RequestBody;
+use crate::api::proxy::{self, ProxyRoutes, UpstreamRequest, UpstreamResponse};
+use crate::api::recorder::Recorder;
 use crate::api::resolver::StubResolver;
 use crate::error::Error;
 use crate::misc::{Renderable, Substitute};
 use crate::model::*;
-use crate::model::persistent::HttpStubResponse;
+use crate::model::persistent::{HttpStubResponse, ResponseCode};
+use crate::utils::js::optic::JsonOptic;
+use crate::utils::transformations::js::JsonTransformations;
+use actix_web::http::header;
 use json_value_merge::Merge;
+use log::{error, info};
 use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 use persistent::State;
 use serde_json::{json, Value};
 
 pub struct ExecHandler {
-    stub_res: StubResolver
+    stub_res: Arc<StubResolver>,
+    journal: Arc<RequestJournal>,
+    callbacks: CallbackEngine,
+    proxy: ProxyRoutes,
+    recorder: Option<Recorder>,
+    resources_dir: Option<PathBuf>,
+    path_prefix: String
+}
+
+pub enum ExecResponse {
+    Stubbed(HttpStubResponse),
+    Proxied(UpstreamResponse)
+}
+
+impl ExecResponse {
+    pub fn get_code(&self) -> u16 {
+        match self {
+            ExecResponse::Stubbed(response) => response.get_code(),
+            ExecResponse::Proxied(response) => response.code
+        }
+    }
 }
 
 impl ExecHandler {
-    pub fn new(stub_res: StubResolver) -> ExecHandler {
-        ExecHandler { stub_res }
+    /// `resources_dir` should be canonical, files outside of it are never served
+    pub fn new(stub_res: Arc<StubResolver>, journal: Arc<RequestJournal>, proxy: ProxyRoutes, recorder: Option<Recorder>, resources_dir: Option<PathBuf>, path_prefix: &str) -> ExecHandler {
+        let callbacks = CallbackEngine::new(stub_res.clone());
+        let path_prefix = format!("/{}", path_prefix.trim_matches('/')).trim_end_matches('/').to_string();
+
+        ExecHandler { stub_res, journal, callbacks, proxy, recorder, resources_dir, path_prefix }
+    }
+
+    /// Extracts stub path from the request path, None means the request is outside of the exec prefix
+    pub fn strip_prefix<'p>(&self, path: &'p str) -> Option<&'p str> {
+        path.strip_prefix(&self.path_prefix).filter(|p| p.starts_with('/'))
     }
 
-    pub async fn exec(&self, with_method: HttpMethod, with_path: String, with_headers: HashMap<String, String>, query_object: Value, body: RequestBody) -> Result<HttpStubResponse, Error> {
-        let (mut stub, state_op) = 
+    /// Responds with a matching stub, requests without one are forwarded to the upstream configured for their path
+    pub async fn exec(&self, with_method: HttpMethod, with_path: String, with_headers: HashMap<String, String>, query_object: Value, body: RequestBody, upstream: UpstreamRequest) -> Result<ExecResponse, Error> {
+        let mut entry = JournalEntry::new(&with_method, &with_path, &with_headers, &query_object, &body);
+
+        let result = match self.exec_stub(with_method.clone(), with_path.clone(), with_headers, query_object.clone(), body, &mut entry).await {
+            Ok(Some((HttpStubResponse::ProxyResponse { uri, patch, delay }, data))) =>
+                self.fetch_and_patch(&uri, patch, delay, &upstream, data).await.map(ExecResponse::Stubbed),
+            Ok(Some((HttpStubResponse::FileResponse { code, headers, path, delay }, _))) =>
+                self.resolve_file(&path).map(|resolved| ExecResponse::Stubbed(match resolved {
+                    Some(resolved) => HttpStubResponse::FileResponse { code, headers, path: resolved.display().to_string(), delay },
+                    None => HttpStubResponse::RawResponse { code: ResponseCode::Code(404), headers: HashMap::new(), body: format!("Can't find {}", path), template: false, delay }
+                })),
+            Ok(Some((response, _))) => Ok(ExecResponse::Stubbed(response)),
+            Ok(None) => self.forward(&with_method, &with_path, &query_object, upstream).await,
+            Err(err) => Err(err)
+        };
+
+        if let Ok(response) = &result {
+            entry.response_code = response.get_code();
+        }
+
+        self.journal.record(entry).await;
+
+        result
+    }
+
+    async fn forward(&self, with_method: &HttpMethod, with_path: &str, query_object: &Value, mut upstream: UpstreamRequest) -> Result<ExecResponse, Error> {
+        let Some(url) = self.proxy.upstream_url(with_path, &upstream.query_string) else {
+            return Err(Error::new(format!("Can't find any stub for [{:?}] {:?}", with_method, with_path)));
+        };
+
+        info!("No stub for [{:?}] {:?}, proxying to {}", with_method, with_path, url);
+
+        if self.recorder.is_some() {
+            // recorded bodies should be readable
+            upstream.headers.remove(header::ACCEPT_ENCODING);
+        }
+
+        let response = match proxy::forward(&url, &upstream).await {
+            Ok(response) => response,
+            Err(err) => {
+                error!("Upstream {} failed: {}", url, err);
+                return Ok(ExecResponse::Proxied(UpstreamResponse::bad_gateway(&url, &err)));
+            }
+        };
+
+        if let Some(recorder) = &self.recorder {
+            recorder.record(with_method, with_path, query_object, &upstream, &response);
+        }
+
+        Ok(ExecResponse::Proxied(response))
+    }
+
+    async fn exec_stub(&self, with_method: HttpMethod, with_path: String, with_headers: HashMap<String, String>, query_object: Value, body: RequestBody, entry: &mut JournalEntry) -> Result<Option<(HttpStubResponse, Value)>, Error> {
+        let mut state_guard = None;
+
+        let (mut stub, mut state_op) = loop {
             match self.stub_res.find_stub_and_state(Scope::Countdown, &with_method, &with_path, &with_headers, &query_object, &body).await?
                 .or(self.stub_res.find_stub_and_state(Scope::Ephemeral, &with_method, &with_path, &with_headers, &query_object, &body).await?)
                 .or(self.stub_res.find_stub_and_state(Scope::Persistent, &with_method, &with_path, &with_headers, &query_object, &body).await?) {
-                    Some((s, sto)) => (s, sto),
-                    None => {
-                        return Err(Error::new(format!("Can't find any stub for [{:?}] {:?}", with_method, with_path)))
-                    }
-                };
+                    // stateful stubs are matched once again under the state lock, which is kept
+                    // until the new state is persisted, so concurrent requests can't interleave
+                    Some((s, _)) if s.is_stateful() && state_guard.is_none() => {
+                        state_guard = Some(self.stub_res.lock_states().await);
+                        continue
+                    },
+                    // countdown stub was exhausted in between, look again
+                    Some((s, _)) if s.scope == Scope::Countdown && !self.stub_res.take_countdown(&s.id).await => continue,
+                    Some((s, sto)) => break (s, sto),
+                    None => return Ok(None)
+                }
+        };
+
+        entry.stub_name = Some(stub.name.clone());
+        entry.stub_id = Some(stub.id);
+        entry.state_id = state_op.as_ref().map(|s| s.id);
 
         let body_json = stub.request.extract_json(&body);
         let groups = stub.extract_groups(&with_path);
@@ -36,7 +143,7 @@ impl ExecHandler {
                 (name, serde_json::from_str(&value).unwrap_or(Value::String(value))))
             ).map(Value::from_iter);
 
-        let data = json!({
+        let mut data = json!({
             "req": body_json,
             "state": state_op.clone().map(|s| s.data),
             "query": query_object,
@@ -45,16 +152,193 @@ impl ExecHandler {
         });
 
         stub.response.substitute(data.clone());
+        let response_data = data.clone();
 
         if let Some(mut persist_spec) = stub.persist {
-            persist_spec.fill(data);
+            persist_spec.fill(data.clone());
             
             let mut current_state = state_op.unwrap_or(State::fresh());
             current_state.data.merge(&persist_spec.render_json());
 
-            self.stub_res.upsert_state(current_state).await;
+            entry.state_id = Some(current_state.id);
+            self.stub_res.upsert_state(current_state.clone()).await?;
+
+            data["state"] = current_state.data.clone();
+            state_op = Some(current_state);
+        }
+
+        drop(state_guard);
+
+        if let Some(callback) = stub.callback {
+            self.callbacks.schedule(callback, data, state_op);
         }
 
-        Ok(stub.response)
+        Ok(Some((stub.response, response_data)))
+    }
+
+    /// None means there is no such file within the resources directory,
+    /// paths escaping it with `..` or symlinks are treated the same way
+    fn resolve_file(&self, path: &str) -> Result<Option<PathBuf>, Error> {
+        let root = self.resources_dir.as_ref()
+            .ok_or_else(|| Error::new(format!("Can't serve {}: resources directory is not set", path)))?;
+
+        match root.join(path.trim_start_matches('/')).canonicalize() {
+            Ok(resolved) if resolved.starts_with(root) && resolved.is_file() => Ok(Some(resolved)),
+            Ok(_) => Ok(None),
+            // `/a.txt/x` fails with ENOTDIR rather than ENOENT
+            Err(err) if matches!(err.kind(), ErrorKind::NotFound | ErrorKind::NotADirectory) => Ok(None),
+            Err(err) => Err(Error::new(format!("Can't serve {}: {}", path, err)))
+        }
     }
-}
\ No newline at end of file
+
+    async fn fetch_and_patch(&self, uri: &str, patch: HashMap<JsonOptic, String>, delay: Option<Duration>, upstream: &UpstreamRequest, data: Value) -> Result<HttpStubResponse, Error> {
+        let mut request = upstream.clone();
+        // the body has to be parsed, so it shouldn't come compressed
+        request.headers.remove(header::ACCEPT_ENCODING);
+
+        let response = proxy::forward(uri, &request).await?;
+
+        let mut body = serde_json::from_slice::<Value>(&response.body)
+            .map_err(|e| Error::new(format!("Upstream {} responded with malformed JSON: {}", uri, e)))?;
+        body.patch_in_place(data, patch);
+
+        let headers = response.headers.iter()
+            .filter_map(|(name, value)| value.to_str().ok().map(|v| (name.to_string(), v.to_string())))
+            .collect();
+
+        Ok(HttpStubResponse::JsonResponse { code: ResponseCode::Code(response.code), headers, body, delay })
+    }
+}
+
+#[cfg(test)]
+mod exec_tests {
+    use crate::api::exec::{ExecHandler, ExecResponse};
+    use crate::api::journal::RequestJournal;
+    use crate::api::model::RequestBody;
+    use crate::api::proxy::{ProxyRoutes, UpstreamRequest};
+    use crate::model::HttpMethod;
+    use crate::model::persistent::{HttpStub, HttpStubResponse, State};
+    use crate::test_utils::{listen, resolver, stub, temp_path};
+    use actix_web::HttpResponse;
+    use actix_web::http::Method;
+    use actix_web::http::header::{self, HeaderMap, HeaderValue};
+    use actix_web::web::Bytes;
+    use futures::future::join_all;
+    use serde_json::json;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    fn handler(stubs: Vec<HttpStub>, resources_dir: Option<PathBuf>) -> ExecHandler {
+        ExecHandler::new(Arc::new(resolver(stubs)), Arc::new(RequestJournal::new(10)), ProxyRoutes::new(None, &[]).unwrap(), None, resources_dir, "")
+    }
+
+    fn upstream() -> UpstreamRequest {
+        UpstreamRequest { method: Method::POST, headers: HeaderMap::new(), query_string: String::new(), body: Bytes::new() }
+    }
+
+    #[actix_web::test]
+    async fn concurrent_persists_are_not_lost() {
+        let stub = stub("counter", json!({
+            "method": "POST",
+            "state": {"kind": {"==": "counter"}},
+            "persist": {"hits": "%{state.hits + 1}"}
+        }));
+        let handler = handler(vec![stub], None);
+        let state = State { data: json!({"kind": "counter", "hits": 0}), ..State::fresh() };
+        handler.stub_res.upsert_state(state.clone()).await.unwrap();
+
+        let results = join_all((0..20).map(|_|
+            handler.exec(HttpMethod::Post, "/counter".to_string(), HashMap::new(), json!({}), RequestBody::AbsentRequestBody, upstream())
+        )).await;
+
+        assert!(results.iter().all(|r| r.as_ref().is_ok_and(|resp| resp.get_code() == 200)));
+        assert_eq!(handler.stub_res.get_state(&state.id).await.unwrap().data, json!({"kind": "counter", "hits": 20}));
+    }
+
+    #[test]
+    fn files_outside_of_resources_dir_are_not_found() {
+        let root = temp_path("files");
+        fs::create_dir_all(root.join("resources")).unwrap();
+        fs::write(root.join("resources").join("a.txt"), "a").unwrap();
+        fs::write(root.join("secret.txt"), "secret").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(root.join("secret.txt"), root.join("resources").join("link.txt")).unwrap();
+
+        let resources = root.join("resources").canonicalize().unwrap();
+        let handler = handler(vec![], Some(resources.clone()));
+        let resolve = |path: &str| handler.resolve_file(path).unwrap();
+
+        let existing = resolve("/a.txt");
+        let missing = resolve("/missing.txt");
+        let traversal = resolve("/../secret.txt");
+        let directory = resolve("/");
+        let symlink = resolve("/link.txt");
+        let below_file = resolve("/a.txt/x");
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(existing, Some(resources.join("a.txt")));
+        assert_eq!(missing, None);
+        assert_eq!(traversal, None);
+        assert_eq!(directory, None);
+        assert_eq!(symlink, None);
+        assert_eq!(below_file, None);
+    }
+
+    #[actix_web::test]
+    async fn unreachable_upstream_is_bad_gateway() {
+        let proxy = ProxyRoutes::new(Some("http://127.0.0.1:1".to_string()), &[]).unwrap();
+        let handler = ExecHandler::new(Arc::new(resolver(vec![])), Arc::new(RequestJournal::new(10)), proxy, None, None, "");
+
+        let response = handler.exec(HttpMethod::Get, "/users".to_string(), HashMap::new(), json!({}), RequestBody::AbsentRequestBody, upstream()).await;
+
+        assert!(response.is_ok_and(|r| r.get_code() == 502));
+    }
+
+    #[actix_web::test]
+    async fn proxy_response_is_patched_with_request_data() {
+        let (address, received) = listen(|req| match req.path() {
+            "/text" => HttpResponse::Ok().content_type("text/plain").body("not json"),
+            _ => HttpResponse::Ok().json(json!({"id": 0, "upstream": true}))
+        });
+
+        let patched = stub("patched", json!({
+            "method": "POST",
+            "path": null,
+            "pathPattern": "/items/(?<id>\\d+)",
+            "state": {"kind": {"==": "item"}},
+            "request": {"mode": "json", "headers": {}, "body": {"name": "Kek"}},
+            "response": {
+                "mode": "proxy",
+                "uri": format!("http://{}/upstream/${{pathParts.id}}", address),
+                "patch": {"id": "${pathParts.id}", "name": "${req.name}", "owner": "${state.owner}"}
+            }
+        }));
+        let broken = stub("broken", json!({
+            "method": "POST",
+            "response": {"mode": "proxy", "uri": format!("http://{}/text", address)}
+        }));
+        let handler = handler(vec![patched, broken], None);
+        handler.stub_res.upsert_state(State { data: json!({"kind": "item", "owner": "Peka"}), ..State::fresh() }).await.unwrap();
+
+        let mut request = upstream();
+        request.headers.insert(header::ACCEPT_ENCODING, HeaderValue::from_static("gzip"));
+        let body = RequestBody::SimpleRequestBody {
+            raw_value: br#"{"name": "Kek"}"#.to_vec(),
+            value: Some(r#"{"name": "Kek"}"#.to_string()),
+            content_type: Some("application/json".to_string())
+        };
+
+        let response = handler.exec(HttpMethod::Post, "/items/42".to_string(), HashMap::new(), json!({}), body, request.clone()).await.unwrap();
+        let failed = handler.exec(HttpMethod::Post, "/broken".to_string(), HashMap::new(), json!({}), RequestBody::AbsentRequestBody, request).await;
+
+        assert!(matches!(response, ExecResponse::Stubbed(HttpStubResponse::JsonResponse { ref body, .. })
+            if *body == json!({"id": 42, "upstream": true, "name": "Kek", "owner": "Peka"})));
+        assert!(failed.is_err());
+
+        let received = received.lock().unwrap();
+        assert_eq!(received[0].path, "/upstream/42");
+        assert!(received.iter().all(|r| !r.headers.contains_key(header::ACCEPT_ENCODING)));
+    }
+}
@@ -0,0 +1,104 @@
+use crate::api::model::RequestBody;
+use crate::model::HttpMethod;
+use crate::predicate_dsl::json::JsonPredicate;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use chrono::{DateTime, Utc};
+use log::error;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JournalEntry {
+    pub id: Uuid,
+    pub timestamp: DateTime<Utc>,
+    pub method: HttpMethod,
+    pub path: String,
+    pub headers: HashMap<String, String>,
+    pub query: Value,
+    pub body: Option<Value>,
+    /// Set instead of `body` when the body is not UTF-8
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body_base64: Option<String>,
+    pub stub_name: Option<String>,
+    pub stub_id: Option<Uuid>,
+    pub state_id: Option<Uuid>,
+    pub response_code: u16,
+    #[serde(skip)]
+    pub request_body: RequestBody
+}
+
+impl JournalEntry {
+    pub fn new(method: &HttpMethod, path: &str, headers: &HashMap<String, String>, query: &Value, body: &RequestBody) -> JournalEntry {
+        JournalEntry {
+            id: Uuid::new_v4(),
+            timestamp: Utc::now(),
+            method: method.clone(),
+            path: path.to_string(),
+            headers: headers.clone(),
+            query: query.clone(),
+            body: body.extract_string().map(|s| serde_json::from_str(&s).unwrap_or(Value::String(s))),
+            body_base64: Some(body.raw_value()).filter(|raw| !raw.is_empty() && body.extract_string().is_none()).map(|raw| STANDARD.encode(raw)),
+            stub_name: None,
+            stub_id: None,
+            state_id: None,
+            response_code: 500,
+            request_body: body.clone()
+        }
+    }
+}
+
+/// Bounded in-memory log of incoming requests, oldest entries are evicted first
+pub struct RequestJournal {
+    capacity: usize,
+    entries: RwLock<VecDeque<JournalEntry>>
+}
+
+impl RequestJournal {
+    pub fn new(capacity: usize) -> RequestJournal {
+        RequestJournal { capacity, entries: RwLock::new(VecDeque::with_capacity(capacity)) }
+    }
+
+    pub async fn record(&self, entry: JournalEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut entries = self.entries.write().await;
+
+        while entries.len() >= self.capacity {
+            entries.pop_front();
+        }
+
+        entries.push_back(entry);
+    }
+
+    pub async fn entries(&self) -> Vec<JournalEntry> {
+        self.entries.read().await.iter().cloned().collect()
+    }
+
+    pub async fn find(&self, predicate: &JsonPredicate) -> Vec<JournalEntry> {
+        self.entries.read().await.iter()
+            .filter(|entry| {
+                let entry_json = serde_json::to_value(entry).unwrap_or(Value::Null);
+
+                match predicate.validate(entry_json) {
+                    Ok(res) => res,
+                    Err(err) => {
+                        error!("{err}");
+                        false
+                    }
+                }
+            })
+            .cloned()
+            .collect()
+    }
+
+    pub async fn clear(&self) {
+        self.entries.write().await.clear();
+    }
+}
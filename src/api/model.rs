@@ -1,17 +1,76 @@
+use crate::api::multipart::Part;
+use crate::error::Error;
+use actix_web::web::Query;
+use serde_json::{Map, Value};
+
 #[derive(Clone)]
 pub enum RequestBody {
     AbsentRequestBody,
+    /// `value` is present for UTF-8 bodies only
     SimpleRequestBody {
         raw_value: Vec<u8>,
-        value: String
+        value: Option<String>,
+        content_type: Option<String>
+    },
+    MultipartRequestBody {
+        raw_value: Vec<u8>,
+        parts: Vec<Part>,
+        content_type: Option<String>
     }
 }
 
 impl RequestBody {
     pub fn extract_string(&self) -> Option<String> {
         match self {
-            RequestBody::SimpleRequestBody { value, .. } => Some(value.clone()),
+            RequestBody::SimpleRequestBody { value, .. } => value.clone(),
+            RequestBody::MultipartRequestBody { raw_value, .. } => String::from_utf8(raw_value.clone()).ok(),
             _ => None
         }
     }
-}
\ No newline at end of file
+
+    pub fn raw_value(&self) -> &[u8] {
+        match self {
+            RequestBody::AbsentRequestBody => &[],
+            RequestBody::SimpleRequestBody { raw_value, .. } => raw_value,
+            RequestBody::MultipartRequestBody { raw_value, .. } => raw_value
+        }
+    }
+
+    pub fn content_type(&self) -> Option<&str> {
+        match self {
+            RequestBody::AbsentRequestBody => None,
+            RequestBody::SimpleRequestBody { content_type, .. } => content_type.as_deref(),
+            RequestBody::MultipartRequestBody { content_type, .. } => content_type.as_deref()
+        }
+    }
+}
+
+/// Decodes query strings and form bodies, values that are valid JSON are typed accordingly
+pub fn urlencoded_to_json_value(encoded: &str) -> Result<Value, Error> {
+    let params = Query::<Vec<(String, String)>>::from_query(encoded)
+        .map_err(Error::from)?.0;
+
+    Ok(Value::from_iter(params.into_iter().map(|(key, value)| (key, serde_json::from_str(value.as_str()).unwrap_or(Value::String(value)) ))))
+}
+
+/// Decodes a form body, unlike query strings repeated fields are collected into arrays
+pub fn form_to_json_value(encoded: &str) -> Result<Value, Error> {
+    let params = Query::<Vec<(String, String)>>::from_query(encoded)
+        .map_err(Error::from)?.0;
+
+    let mut fields: Map<String, Value> = Map::new();
+
+    for (key, value) in params {
+        let value = serde_json::from_str(value.as_str()).unwrap_or(Value::String(value));
+
+        match fields.get_mut(&key) {
+            Some(Value::Array(values)) => values.push(value),
+            _ => drop(fields.insert(key, Value::Array(vec![value])))
+        }
+    }
+
+    Ok(Value::Object(fields.into_iter().map(|(key, mut values)| match values.as_array_mut() {
+        Some(single) if single.len() == 1 => (key, single.remove(0)),
+        _ => (key, values)
+    }).collect()))
+}
@@ -0,0 +1,128 @@
+use crate::error::Error;
+use mime::Mime;
+use serde_json::Value;
+use std::collections::HashMap;
+
+#[derive(Clone, Debug)]
+pub struct Part {
+    pub name: String,
+    pub filename: Option<String>,
+    /// Header names are lowercase
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>
+}
+
+impl Part {
+    pub fn extract_string(&self) -> Option<String> {
+        String::from_utf8(self.body.clone()).ok()
+    }
+
+    /// Parts declaring a JSON content type are parsed, other textual parts are kept as strings
+    pub fn guess_json(&self) -> Value {
+        let text = self.extract_string();
+
+        if self.headers.get("content-type").is_some_and(|ct| ct.contains("json")) {
+            text.as_deref().and_then(|t| serde_json::from_str(t).ok()).unwrap_or(Value::Null)
+        } else {
+            text.map(Value::String).unwrap_or(Value::Null)
+        }
+    }
+}
+
+/// Returns None for content types other than multipart/form-data
+pub fn boundary_of(content_type: &str) -> Option<String> {
+    let mime = content_type.parse::<Mime>().ok()?;
+
+    if mime.type_() == mime::MULTIPART && mime.subtype() == mime::FORM_DATA {
+        mime.get_param(mime::BOUNDARY).map(|b| b.to_string())
+    } else {
+        None
+    }
+}
+
+pub fn parse(boundary: &str, body: &[u8]) -> Result<Vec<Part>, Error> {
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let mut parts = Vec::new();
+
+    let mut rest = find(body, &delimiter)
+        .map(|pos| &body[pos + delimiter.len()..])
+        .ok_or_else(|| Error::new("Multipart body has no parts".to_string()))?;
+
+    loop {
+        if rest.starts_with(b"--") {
+            return Ok(parts);
+        }
+
+        rest = rest.strip_prefix(b"\r\n").ok_or_else(|| Error::new("Malformed multipart delimiter".to_string()))?;
+
+        let headers_end = find(rest, b"\r\n\r\n").ok_or_else(|| Error::new("Multipart part has no headers".to_string()))?;
+        let headers = parse_headers(&rest[..headers_end])?;
+        rest = &rest[headers_end + 4..];
+
+        let body_end = find(rest, &[b"\r\n".as_slice(), &delimiter].concat())
+            .ok_or_else(|| Error::new("Multipart body is not terminated".to_string()))?;
+        let part_body = rest[..body_end].to_vec();
+        rest = &rest[body_end + 2 + delimiter.len()..];
+
+        let disposition = headers.get("content-disposition")
+            .map(|cd| disposition_params(cd))
+            .ok_or_else(|| Error::new("Multipart part has no Content-Disposition".to_string()))?;
+        let name = disposition.get("name").cloned()
+            .ok_or_else(|| Error::new("Multipart part has no name".to_string()))?;
+
+        parts.push(Part { name, filename: disposition.get("filename").cloned(), headers, body: part_body });
+    }
+}
+
+// ---- private stuff ----
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+fn parse_headers(raw: &[u8]) -> Result<HashMap<String, String>, Error> {
+    let text = std::str::from_utf8(raw).map_err(Error::from)?;
+
+    text.split("\r\n")
+        .map(|line| line.split_once(':')
+            .map(|(name, value)| (name.trim().to_lowercase(), value.trim().to_string()))
+            .ok_or_else(|| Error::new(format!("Malformed multipart header: {}", line))))
+        .collect()
+}
+
+fn disposition_params(disposition: &str) -> HashMap<String, String> {
+    disposition.split(';')
+        .skip(1)
+        .filter_map(|param| param.split_once('='))
+        .map(|(key, value)| (key.trim().to_lowercase(), value.trim().trim_matches('"').to_string()))
+        .collect()
+}
+
+#[cfg(test)]
+mod multipart_tests {
+    use crate::api::multipart::{boundary_of, parse};
+
+    #[test]
+    fn parts_are_parsed() {
+        let body = [
+            b"--XyZ\r\nContent-Disposition: form-data; name=\"meta\"\r\nContent-Type: application/json\r\n\r\n{\"id\": 42}\r\n".as_slice(),
+            b"--XyZ\r\nContent-Disposition: form-data; name=\"file\"; filename=\"a.bin\"\r\n\r\n\x00\xff\r\n".as_slice(),
+            b"--XyZ--\r\n".as_slice()
+        ].concat();
+
+        let boundary = boundary_of("multipart/form-data; boundary=\"XyZ\"").unwrap();
+        let parts = parse(&boundary, &body).unwrap();
+
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].name, "meta");
+        assert_eq!(parts[0].guess_json(), serde_json::json!({"id": 42}));
+        assert_eq!(parts[1].filename.as_deref(), Some("a.bin"));
+        assert_eq!(parts[1].body, vec![0x00, 0xff]);
+    }
+
+    #[test]
+    fn other_content_types_have_no_boundary() {
+        assert_eq!(boundary_of("application/json"), None);
+        assert_eq!(boundary_of("multipart/mixed; boundary=XyZ"), None);
+    }
+}
@@ -0,0 +1,137 @@
+use crate::error::Error;
+use actix_web::http::Method;
+use actix_web::http::header::{self, HeaderMap, HeaderName, HeaderValue};
+use actix_web::web::Bytes;
+use awc::Client;
+use log::info;
+use serde_json::json;
+
+const MAX_UPSTREAM_BODY_SIZE: usize = 64 * 1024 * 1024;
+
+/// Original request as it was received, forwarded to upstream verbatim
+#[derive(Clone)]
+pub struct UpstreamRequest {
+    pub method: Method,
+    pub headers: HeaderMap,
+    pub query_string: String,
+    pub body: Bytes
+}
+
+pub struct UpstreamResponse {
+    pub code: u16,
+    pub headers: HeaderMap,
+    pub body: Bytes
+}
+
+impl UpstreamResponse {
+    /// Stands in for an upstream that couldn't be reached
+    pub fn bad_gateway(url: &str, err: &Error) -> UpstreamResponse {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+
+        let body = json!({"error": format!("Upstream {} failed: {}", url, err)}).to_string();
+
+        UpstreamResponse { code: 502, headers, body: Bytes::from(body) }
+    }
+}
+
+/// Upstream URLs unmatched requests are forwarded to, the longest matching path prefix wins
+pub struct ProxyRoutes {
+    routes: Vec<(String, String)>
+}
+
+impl ProxyRoutes {
+    /// `default` serves any path, each of `prefixed` is a `PREFIX=URL` pair
+    pub fn new(default: Option<String>, prefixed: &[String]) -> Result<ProxyRoutes, Error> {
+        let mut routes = Vec::new();
+
+        for route in prefixed {
+            let (prefix, url) = route.split_once('=')
+                .ok_or_else(|| Error::new(format!("Proxy route {} should be in PREFIX=URL form", route)))?;
+            routes.push((format!("/{}", prefix.trim_matches('/')).trim_end_matches('/').to_string(), url.to_string()));
+        }
+
+        if let Some(url) = default {
+            routes.push((String::new(), url));
+        }
+
+        routes.sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+
+        Ok(ProxyRoutes { routes })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+
+    pub fn upstream_url(&self, path: &str, query_string: &str) -> Option<String> {
+        self.routes.iter()
+            .find(|(prefix, _)| path.strip_prefix(prefix.as_str()).is_some_and(|rest| rest.is_empty() || rest.starts_with('/')))
+            .map(|(_, url)| {
+                let url = format!("{}{}", url.trim_end_matches('/'), path);
+                if query_string.is_empty() { url } else { format!("{}?{}", url, query_string) }
+            })
+    }
+}
+
+pub async fn forward(url: &str, request: &UpstreamRequest) -> Result<UpstreamResponse, Error> {
+    let client = Client::builder().disable_redirects().finish();
+    let mut upstream_request = client.request(request.method.clone(), url).no_decompress();
+
+    for (name, value) in request.headers.iter().filter(|(name, _)| !is_hop_by_hop(name)) {
+        upstream_request = upstream_request.append_header((name.clone(), value.clone()));
+    }
+
+    info!("Forwarding [{}] {}", request.method, url);
+
+    let mut response = if request.body.is_empty() {
+        upstream_request.send().await
+    } else {
+        upstream_request.send_body(request.body.clone()).await
+    }.map_err(Error::from)?;
+
+    info!("Upstream [{}] {} responded with {}", request.method, url, response.status());
+
+    let mut headers = HeaderMap::new();
+    for (name, value) in response.headers().iter().filter(|(name, _)| !is_hop_by_hop(name)) {
+        headers.append(name.clone(), value.clone());
+    }
+
+    let body = response.body().limit(MAX_UPSTREAM_BODY_SIZE).await.map_err(Error::from)?;
+
+    Ok(UpstreamResponse { code: response.status().as_u16(), headers, body })
+}
+
+// ---- private stuff ----
+
+fn is_hop_by_hop(name: &HeaderName) -> bool {
+    [
+        header::CONNECTION, header::CONTENT_LENGTH, header::HOST, header::PROXY_AUTHENTICATE, header::PROXY_AUTHORIZATION,
+        header::TE, header::TRAILER, header::TRANSFER_ENCODING, header::UPGRADE
+    ].contains(name) || name.as_str() == "keep-alive"
+}
+
+#[cfg(test)]
+mod proxy_tests {
+    use crate::api::proxy::ProxyRoutes;
+
+    #[test]
+    fn longest_prefix_wins() {
+        let routes = ProxyRoutes::new(
+            Some("http://default:8080/".to_string()),
+            &["/users=http://users:8080".to_string(), "/users/admins/=http://admins:8080".to_string()]
+        ).unwrap();
+
+        assert_eq!(routes.upstream_url("/users/1", ""), Some("http://users:8080/users/1".to_string()));
+        assert_eq!(routes.upstream_url("/users/admins", "q=1"), Some("http://admins:8080/users/admins?q=1".to_string()));
+        assert_eq!(routes.upstream_url("/usersettings", ""), Some("http://default:8080/usersettings".to_string()));
+    }
+
+    #[test]
+    fn no_route_without_default() {
+        let routes = ProxyRoutes::new(None, &["/users=http://users:8080".to_string()]).unwrap();
+
+        assert_eq!(routes.upstream_url("/orders", ""), None);
+        assert!(ProxyRoutes::new(None, &["/users".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,155 @@
+use crate::api::proxy::{UpstreamRequest, UpstreamResponse};
+use crate::error::Error;
+use crate::model::{HttpMethod, Scope};
+use crate::model::persistent::{HttpStub, HttpStubRequest, HttpStubResponse, ResponseCode};
+use crate::predicate_dsl::binary::BinaryPredicate;
+use crate::predicate_dsl::keyword::Keyword;
+use crate::storage::write_json_atomically;
+use crate::utils::js::optic::JsonOptic;
+use actix_web::http::header::{self, HeaderMap};
+use chrono::Utc;
+use log::{error, info};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+/// Writes proxied exchanges into a mock file, a later exchange with the same request replaces the earlier one
+pub struct Recorder {
+    path: PathBuf,
+    stubs: Mutex<Vec<HttpStub>>
+}
+
+impl Recorder {
+    /// Stubs already present in the file are kept
+    pub fn open(path: PathBuf) -> Result<Recorder, Error> {
+        let stubs = if path.exists() {
+            let contents = fs::read_to_string(&path).map_err(Error::from)?;
+            serde_json::from_str::<Vec<HttpStub>>(&contents)
+                .map_err(|e| Error::new(format!("Can't parse recorded stubs {}: {}", path.display(), e)))?
+        } else {
+            vec![]
+        };
+
+        Ok(Recorder { path, stubs: Mutex::new(stubs) })
+    }
+
+    pub fn record(&self, method: &HttpMethod, path: &str, query: &Value, request: &UpstreamRequest, response: &UpstreamResponse) {
+        let stub = to_stub(method, path, query, request, response);
+
+        let mut stubs = self.stubs.lock().unwrap();
+
+        match stubs.iter_mut().find(|s| is_same_request(s, &stub)) {
+            Some(existing) => *existing = HttpStub { id: existing.id, ..stub },
+            None => stubs.push(stub)
+        }
+
+        match write_json_atomically(&self.path, &*stubs) {
+            Ok(_) => info!("Recorded [{:?}] {:?} into {}", method, path, self.path.display()),
+            Err(err) => error!("Failed to write recorded stubs into {}: {}", self.path.display(), err)
+        }
+    }
+}
+
+// ---- private stuff ----
+
+fn to_stub(method: &HttpMethod, path: &str, query: &Value, request: &UpstreamRequest, response: &UpstreamResponse) -> HttpStub {
+    let query = query.as_object().into_iter().flatten()
+        .map(|(key, value)| (JsonOptic::from_path(key), HashMap::from([(Keyword::Equals, value.clone())])))
+        .collect::<HashMap<_, _>>();
+
+    let stub_request = if request.body.is_empty() {
+        HttpStubRequest::RequestWithoutBody { headers: HashMap::new(), query }
+    } else {
+        match String::from_utf8(request.body.to_vec()) {
+            Ok(body) => match parse_json(&request.headers, &body) {
+                Some(json) => HttpStubRequest::JsonRequest { headers: HashMap::new(), query, body: json },
+                None => HttpStubRequest::RawRequest { headers: HashMap::new(), query, body }
+            },
+            Err(_) => HttpStubRequest::BinaryRequest { headers: HashMap::new(), query, body: BinaryPredicate::of_content(request.body.to_vec()) }
+        }
+    };
+
+    let headers = response.headers.iter()
+        .filter(|(name, _)| **name != header::DATE && **name != header::CONTENT_ENCODING)
+        .filter_map(|(name, value)| value.to_str().ok().map(|v| (name.to_string(), v.to_string())))
+        .collect::<HashMap<_, _>>();
+
+    let stub_response = match String::from_utf8(response.body.to_vec()) {
+        Ok(body) => match parse_json(&response.headers, &body) {
+            Some(json) => HttpStubResponse::JsonResponse { code: ResponseCode::Code(response.code), headers, body: json, delay: None },
+            None => HttpStubResponse::RawResponse { code: ResponseCode::Code(response.code), headers, body, template: false, delay: None }
+        },
+        Err(_) => HttpStubResponse::BinaryResponse { code: ResponseCode::Code(response.code), headers, body: response.body.to_vec(), delay: None }
+    };
+
+    HttpStub {
+        id: Uuid::new_v4(),
+        created: Utc::now(),
+        scope: Scope::Persistent,
+        times: None,
+        name: format!("{} {}", method.as_str(), path),
+        method: method.clone(),
+        path: Some(path.to_string()),
+        path_pattern: None,
+        seed: None,
+        state: None,
+        request: stub_request,
+        persist: None,
+        response: stub_response,
+        callback: None,
+        source: None
+    }
+}
+
+fn parse_json(headers: &HeaderMap, body: &str) -> Option<Value> {
+    let is_json = headers.get(header::CONTENT_TYPE)
+        .and_then(|ct| ct.to_str().ok())
+        .is_some_and(|ct| ct.contains("json"));
+
+    if is_json { serde_json::from_str(body).ok() } else { None }
+}
+
+fn is_same_request(one: &HttpStub, other: &HttpStub) -> bool {
+    one.method == other.method && one.path == other.path &&
+        serde_json::to_value(&one.request).ok() == serde_json::to_value(&other.request).ok()
+}
+
+#[cfg(test)]
+mod recorder_tests {
+    use crate::api::proxy::{UpstreamRequest, UpstreamResponse};
+    use crate::api::recorder::to_stub;
+    use crate::model::HttpMethod;
+    use crate::model::persistent::{HttpStubRequest, HttpStubResponse, ResponseCode};
+    use actix_web::http::Method;
+    use actix_web::http::header::{self, HeaderMap, HeaderValue};
+    use actix_web::web::Bytes;
+    use serde_json::json;
+
+    fn json_headers() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        headers
+    }
+
+    #[test]
+    fn modes_follow_content_type() {
+        let request = UpstreamRequest {
+            method: Method::POST,
+            headers: json_headers(),
+            query_string: "page=2".to_string(),
+            body: Bytes::from_static(br#"{"name": "Peka"}"#)
+        };
+        let response = UpstreamResponse { code: 201, headers: HeaderMap::new(), body: Bytes::from_static(b"created") };
+
+        let stub = to_stub(&HttpMethod::Post, "/users", &json!({"page": 2}), &request, &response);
+
+        assert_eq!(stub.path.as_deref(), Some("/users"));
+        assert!(matches!(stub.request, HttpStubRequest::JsonRequest { ref body, .. } if *body == json!({"name": "Peka"})));
+        assert!(stub.request.check_query_params(json!({"page": 2})));
+        assert!(!stub.request.check_query_params(json!({"page": 3})));
+        assert!(matches!(stub.response, HttpStubResponse::RawResponse { code: ResponseCode::Code(201), ref body, .. } if body == "created"));
+    }
+}
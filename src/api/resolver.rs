@@ -1,34 +1,52 @@
 use crate::api::model::RequestBody;
 use crate::error::Error;
-use crate::misc::Renderable;
+use crate::misc::{Renderable, Substitute};
 use crate::model::*;
 use crate::predicate_dsl::json::JsonPredicate;
+use crate::storage::state::StateStorage;
+use crate::storage::stub::StubStorage;
+use chrono::{TimeDelta, Utc};
 use futures::future::join_all;
 use log::{error, info};
 use persistent::{HttpStub, State};
 use serde_json::{json, Value};
 use std::collections::HashMap;
-use tokio::sync::RwLock;
+use tokio::sync::{Mutex, MutexGuard, RwLock};
 use uuid::Uuid;
 
+/// Outcome of changing a stub through the admin API
+pub enum StubChange {
+    Applied,
+    NotFound,
+    /// File-defined stubs can't be changed, carries the stub label
+    ReadOnly(String)
+}
+
 pub struct StubResolver {
-    mocks: Vec<HttpStub>,
-    states: RwLock<HashMap<Uuid, State>>
+    mocks: RwLock<Vec<HttpStub>>,
+    states: Box<dyn StateStorage>,
+    state_lock: Mutex<()>,
+    stub_storage: Box<dyn StubStorage>,
+    ephemeral_ttl: TimeDelta
 }
 
 impl StubResolver {
-    pub fn new(mocks: Vec<HttpStub>, states: RwLock<HashMap<Uuid, State>>) -> StubResolver {
-        StubResolver { mocks, states }
+    pub fn new(mocks: RwLock<Vec<HttpStub>>, states: Box<dyn StateStorage>, stub_storage: Box<dyn StubStorage>, ephemeral_ttl: TimeDelta) -> StubResolver {
+        StubResolver { mocks, states, state_lock: Mutex::new(()), stub_storage, ephemeral_ttl }
     }
 
     pub async fn find_stub_and_state(&self, in_scope: Scope, with_method: &HttpMethod, with_path: &str, with_headers: &HashMap<String, String>, query_object: &Value, body: &RequestBody) -> Result<Option<(HttpStub, Option<State>)>, Error> {
         info!("Searching searching stubs for {:?} of scope {:?}", with_path, in_scope);
 
-        let candidates0: Vec<HttpStub> = self.mocks.clone().into_iter()
+        let now = Utc::now();
+
+        let candidates0: Vec<HttpStub> = self.mocks.read().await.iter()
             .filter(|m| m.scope == in_scope && m.method == *with_method &&
                 (m.path.as_ref().is_some_and(|p| *p == with_path) || m.path_pattern.as_ref().is_some_and(|pp| pp.is_match(with_path))) &&
-                (in_scope != Scope::Countdown || m.times.is_some_and(|rem| rem > 0))
+                (in_scope != Scope::Countdown || m.times.is_some_and(|rem| rem > 0)) &&
+                !m.is_expired(self.ephemeral_ttl, now)
             )
+            .cloned()
             .collect();
 
         if candidates0.is_empty() {
@@ -80,36 +98,34 @@ impl StubResolver {
                 }
                 let predicate = JsonPredicate::from_spec(state_spec);
 
-                for state in self.states.read().await.values() {
-                    match predicate.validate(state.data.clone()) {
-                        Ok(true) => matching_states.push(state.clone()),
-                        Ok(false) => (),
-                        Err(err) => error!("{err}"),
-                    }
-                }
+                matching_states = self.states.find(&predicate);
             }
 
             (s, matching_states)
         })).await;
 
         if candidates4.iter().any(|(_, states)| states.len() > 1) {
-            error!("For one or more stubs, multiple suitable states were found");
-            return Err(Error::new("For one or more stubs, multiple suitable states were found".to_string()));
+            let message = format!("For one or more stubs, multiple suitable states were found: {}", labels(candidates4.iter().filter(|(_, states)| states.len() > 1)));
+            error!("{message}");
+            return Err(Error::new(message));
         }
 
         if candidates4.iter().filter(|(_, states)| !states.is_empty()).count() > 1 {
-            error!("For more than one stub, suitable states were found");
-            return Err(Error::new("For more than one stub, suitable states were found".to_string()));
+            let message = format!("For more than one stub, suitable states were found: {}", labels(candidates4.iter().filter(|(_, states)| !states.is_empty())));
+            error!("{message}");
+            return Err(Error::new(message));
         }
 
         if candidates4.len() > 1 && candidates4.iter().all(|(stub, states)| stub.state.is_some() && states.is_empty()) {
-            error!("No suitable state found for any stub");
-            return Err(Error::new("No suitable state found for any stub".to_string()));
+            let message = format!("No suitable state found for any stub: {}", labels(candidates4.iter()));
+            error!("{message}");
+            return Err(Error::new(message));
         }
 
         if candidates4.len() > 1 && candidates4.iter().all(|(stub, _)| stub.state.is_none()) {
-            error!("More than one stateless stub found");
-            return Err(Error::new("More than one stateless stub found".to_string()));
+            let message = format!("More than one stateless stub found: {}", labels(candidates4.iter()));
+            error!("{message}");
+            return Err(Error::new(message));
         }
 
         let res = candidates4.iter().find(|(_, states)| states.len() == 1).or(candidates4.iter().find(|(stub, _)| stub.state.is_none()));
@@ -117,9 +133,265 @@ impl StubResolver {
         Ok(res.map(|(stub, states)| (stub.clone(), states.first().map(|s| s.clone()))))
     }
 
-    //TODO: move to a separate abstraction?
-    pub async fn upsert_state(&self, state: State) {
-        let mut states = self.states.write().await;
-        states.insert(state.id, state);
+    pub async fn stubs(&self) -> Vec<HttpStub> {
+        self.mocks.read().await.clone()
+    }
+
+    pub async fn get_stub(&self, id: &Uuid) -> Option<HttpStub> {
+        self.mocks.read().await.iter().find(|s| s.id == *id).cloned()
+    }
+
+    pub async fn add_stub(&self, stub: HttpStub) -> Result<(), Error> {
+        let mut mocks = self.mocks.write().await;
+
+        if mocks.iter().any(|s| s.id == stub.id) {
+            return Err(Error::new(format!("Stub with id {} already exists", stub.id)));
+        }
+
+        self.stub_storage.upsert(&stub)?;
+
+        mocks.push(stub);
+        Ok(())
+    }
+
+    pub async fn replace_stub(&self, mut stub: HttpStub) -> Result<StubChange, Error> {
+        let mut mocks = self.mocks.write().await;
+
+        match mocks.iter_mut().find(|s| s.id == stub.id) {
+            Some(existing) if existing.source.is_some() => Ok(StubChange::ReadOnly(existing.label())),
+            Some(existing) => {
+                stub.created = existing.created;
+                self.stub_storage.upsert(&stub)?;
+                *existing = stub;
+                Ok(StubChange::Applied)
+            },
+            None => Ok(StubChange::NotFound)
+        }
+    }
+
+    /// Swaps stubs loaded from files with a new set, keeping runtime-created stubs and all states.
+    /// Stubs that were loaded before keep their creation time and remaining countdown
+    pub async fn replace_file_stubs(&self, stubs: Vec<HttpStub>) {
+        let mut mocks = self.mocks.write().await;
+        let (previous, runtime): (Vec<_>, Vec<_>) = mocks.drain(..).partition(|s| s.source.is_some());
+        let previous = previous.into_iter().map(|s| (s.id, s)).collect::<HashMap<_, _>>();
+
+        *mocks = runtime;
+        mocks.extend(stubs.into_iter().map(|mut stub| {
+            if let Some(known) = previous.get(&stub.id) {
+                stub.created = known.created;
+
+                if stub.scope == Scope::Countdown && known.scope == Scope::Countdown {
+                    stub.times = known.times;
+                }
+            }
+
+            stub
+        }));
     }
-}
\ No newline at end of file
+
+    /// Consumes one use of a countdown stub, retiring it when no uses are left.
+    /// Returns false if the stub was already exhausted by a concurrent request
+    pub async fn take_countdown(&self, id: &Uuid) -> bool {
+        let mut mocks = self.mocks.write().await;
+
+        let (remaining, persisted) = match mocks.iter_mut().find(|s| s.id == *id) {
+            Some(stub) if stub.times.is_some_and(|rem| rem > 0) => {
+                let remaining = stub.times.unwrap_or(0) - 1;
+                stub.times = Some(remaining);
+
+                let persisted = match (&stub.source, remaining) {
+                    (Some(_), _) => Ok(()),
+                    (None, 0) => self.stub_storage.remove(id),
+                    (None, _) => self.stub_storage.upsert(stub)
+                };
+
+                (remaining, persisted)
+            },
+            _ => return false
+        };
+
+        if let Err(err) = persisted {
+            error!("Failed to persist countdown of stub {}: {}", id, err);
+        }
+
+        // exhausted file-defined stubs stay around, so reloading the file doesn't bring them back
+        if remaining == 0 {
+            info!("Countdown stub {} is exhausted and will be retired", id);
+            mocks.retain(|s| s.id != *id || s.source.is_some());
+        }
+
+        true
+    }
+
+    /// Expired file-defined stubs are kept, so that reloading the file doesn't bring them back;
+    /// they never match anyway
+    pub async fn purge_expired(&self) -> usize {
+        let now = Utc::now();
+        let mut mocks = self.mocks.write().await;
+        let (expired, alive): (Vec<_>, Vec<_>) = mocks.drain(..).partition(|s| s.source.is_none() && s.is_expired(self.ephemeral_ttl, now));
+        *mocks = alive;
+
+        for stub in expired.iter() {
+            if let Err(err) = self.stub_storage.remove(&stub.id) {
+                error!("Failed to remove expired stub {} from storage: {}", stub.label(), err);
+            }
+        }
+
+        expired.len()
+    }
+
+    pub async fn remove_stub(&self, id: &Uuid) -> Result<StubChange, Error> {
+        let mut mocks = self.mocks.write().await;
+
+        match mocks.iter().find(|s| s.id == *id) {
+            Some(existing) if existing.source.is_some() => Ok(StubChange::ReadOnly(existing.label())),
+            Some(_) => {
+                self.stub_storage.remove(id)?;
+                mocks.retain(|s| s.id != *id);
+                Ok(StubChange::Applied)
+            },
+            None => Ok(StubChange::NotFound)
+        }
+    }
+
+    /// Evaluates the seed of a stub and stores it as a new state.
+    /// Seeded state shares id with the stub, so a state that survived a restart is not seeded twice
+    pub async fn seed_state(&self, stub: &HttpStub) {
+        let _guard = self.lock_states().await;
+
+        if self.states.get(&stub.id).is_some() {
+            return;
+        }
+
+        if let Some(mut seed) = stub.seed.clone() {
+            seed.substitute(json!({
+                "stub": {
+                    "id": stub.id,
+                    "name": stub.name
+                }
+            }));
+
+            let state = State { id: stub.id, data: seed, ..State::fresh() };
+            info!("Seeding state {} for stub {}", state.id, stub.label());
+
+            if let Err(err) = self.upsert_state(state).await {
+                error!("Failed to seed state for stub {}: {}", stub.label(), err);
+            }
+        }
+    }
+
+    /// Serializes read-modify-write cycles over states: whoever holds the guard
+    /// can match states and persist changes without losing concurrent updates.
+    /// Every write of states should happen under it
+    pub async fn lock_states(&self) -> MutexGuard<'_, ()> {
+        self.state_lock.lock().await
+    }
+
+    pub async fn get_state(&self, id: &Uuid) -> Option<State> {
+        self.states.get(id)
+    }
+
+    pub async fn upsert_state(&self, state: State) -> Result<(), Error> {
+        self.states.upsert(state)
+    }
+
+    pub async fn states(&self) -> Vec<State> {
+        self.states.all()
+    }
+
+    pub async fn find_states(&self, predicate: &JsonPredicate) -> Vec<State> {
+        self.states.find(predicate)
+    }
+
+    pub async fn remove_state(&self, id: &Uuid) -> Result<bool, Error> {
+        self.states.remove(id)
+    }
+
+    pub async fn remove_states(&self, predicate: &JsonPredicate) -> Result<usize, Error> {
+        self.states.remove_matching(predicate)
+    }
+}
+
+fn labels<'s>(candidates: impl Iterator<Item = &'s (HttpStub, Vec<State>)>) -> String {
+    candidates.map(|(stub, _)| stub.label()).collect::<Vec<_>>().join(", ")
+}
+
+#[cfg(test)]
+mod resolver_tests {
+    use crate::api::model::RequestBody;
+    use crate::model::{HttpMethod, Scope};
+    use crate::model::persistent::{HttpStub, State};
+    use crate::test_utils::{resolver, stub};
+    use chrono::{TimeDelta, Utc};
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    fn file_stub(name: &str, scope: Scope, times: Option<i64>) -> HttpStub {
+        let mut stub = stub(name, json!({
+            "id": uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_URL, name.as_bytes()),
+            "scope": scope,
+            "times": times
+        }));
+        stub.source = Some("mocks.json".to_string());
+        stub
+    }
+
+    #[actix_web::test]
+    async fn reload_keeps_countdown_and_creation_time() {
+        let countdown = file_stub("countdown", Scope::Countdown, Some(2));
+        let once = file_stub("once", Scope::Countdown, Some(1));
+        let ephemeral = file_stub("ephemeral", Scope::Ephemeral, None);
+        let resolver = resolver(vec![countdown.clone(), once.clone(), ephemeral.clone()]);
+
+        assert!(resolver.take_countdown(&countdown.id).await);
+        assert!(resolver.take_countdown(&once.id).await);
+        assert!(!resolver.take_countdown(&once.id).await);
+
+        resolver.replace_file_stubs(vec![
+            file_stub("countdown", Scope::Countdown, Some(2)),
+            file_stub("once", Scope::Countdown, Some(1)),
+            file_stub("ephemeral", Scope::Ephemeral, None)
+        ]).await;
+
+        assert_eq!(resolver.get_stub(&countdown.id).await.unwrap().times, Some(1));
+        assert_eq!(resolver.get_stub(&once.id).await.unwrap().times, Some(0));
+        assert_eq!(resolver.get_stub(&ephemeral.id).await.unwrap().created, ephemeral.created);
+    }
+
+    #[actix_web::test]
+    async fn expired_file_stubs_are_not_revived_by_reload() {
+        let fresh = file_stub("expired", Scope::Ephemeral, None);
+        let expired = HttpStub { created: fresh.created - TimeDelta::seconds(120), ..fresh };
+        let resolver = resolver(vec![expired.clone()]);
+
+        assert_eq!(resolver.purge_expired().await, 0);
+        resolver.replace_file_stubs(vec![file_stub("expired", Scope::Ephemeral, None)]).await;
+
+        let reloaded = resolver.get_stub(&expired.id).await.unwrap();
+        assert_eq!(reloaded.created, expired.created);
+        assert!(reloaded.is_expired(TimeDelta::seconds(60), Utc::now()));
+    }
+
+    #[actix_web::test]
+    async fn seeded_state_is_found_and_kept() {
+        let seeded = stub("seeded", json!({
+            "id": uuid::Uuid::new_v4(),
+            "seed": {"kind": "%{stub.name.toUpperCase()}", "owner": "${stub.id}"},
+            "state": {"kind": {"==": "SEEDED"}}
+        }));
+        let resolver = resolver(vec![seeded.clone()]);
+
+        resolver.seed_state(&seeded).await;
+
+        let found = resolver.find_stub_and_state(Scope::Persistent, &HttpMethod::Get, "/seeded", &HashMap::new(), &json!({}), &RequestBody::AbsentRequestBody).await.unwrap();
+        let state = found.and_then(|(_, state)| state).unwrap();
+        assert_eq!(state.id, seeded.id);
+        assert_eq!(state.data, json!({"kind": "SEEDED", "owner": seeded.id.to_string()}));
+
+        resolver.upsert_state(State { data: json!({"kind": "SEEDED", "visits": 1}), ..state }).await.unwrap();
+        resolver.seed_state(&seeded).await;
+
+        assert_eq!(resolver.get_state(&seeded.id).await.unwrap().data, json!({"kind": "SEEDED", "visits": 1}));
+    }
+}
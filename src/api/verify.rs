@@ -0,0 +1,153 @@
+use crate::api::journal::JournalEntry;
+use crate::model::HttpMethod;
+use crate::model::persistent::HttpStubRequest;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerificationRequest {
+    #[serde(default)]
+    pub method: Option<HttpMethod>,
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(with = "serde_regex")]
+    #[serde(default)]
+    pub path_pattern: Option<Regex>,
+    #[serde(default)]
+    pub request: Option<HttpStubRequest>,
+    #[serde(default)]
+    pub times: Option<usize>
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NearMiss {
+    pub entry: JournalEntry,
+    pub mismatches: Vec<String>
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerificationResult {
+    pub count: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expected: Option<usize>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub matched: Vec<JournalEntry>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub near_misses: Vec<NearMiss>
+}
+
+impl VerificationRequest {
+    pub fn verify(&self, entries: Vec<JournalEntry>) -> VerificationResult {
+        let mut matched = Vec::new();
+        let mut near_misses = Vec::new();
+
+        for entry in entries {
+            if !self.check_route(&entry) {
+                continue;
+            }
+
+            let mismatches = self.check_request(&entry);
+
+            if mismatches.is_empty() {
+                matched.push(entry);
+            } else {
+                near_misses.push(NearMiss { entry, mismatches });
+            }
+        }
+
+        let count = matched.len();
+
+        match self.times {
+            Some(expected) if expected != count => VerificationResult { count, expected: Some(expected), matched, near_misses },
+            _ => VerificationResult { count, expected: None, matched: vec![], near_misses: vec![] }
+        }
+    }
+
+    fn check_route(&self, entry: &JournalEntry) -> bool {
+        self.method.as_ref().is_none_or(|m| *m == entry.method) &&
+            self.path.as_ref().is_none_or(|p| *p == entry.path) &&
+            self.path_pattern.as_ref().is_none_or(|pp| pp.is_match(&entry.path))
+    }
+
+    fn check_request(&self, entry: &JournalEntry) -> Vec<String> {
+        let mut mismatches = Vec::new();
+
+        if let Some(request) = &self.request {
+            if !request.check_query_params(entry.query.clone()) {
+                mismatches.push("query".to_string());
+            }
+
+            if !request.check_headers(entry.headers.clone()) {
+                mismatches.push("headers".to_string());
+            }
+
+            if !request.check_body(&entry.request_body) {
+                mismatches.push("body".to_string());
+            }
+        }
+
+        mismatches
+    }
+}
+
+impl VerificationResult {
+    pub fn is_satisfied(&self) -> bool {
+        self.expected.is_none_or(|expected| expected == self.count)
+    }
+}
+
+#[cfg(test)]
+mod verify_tests {
+    use crate::api::journal::JournalEntry;
+    use crate::api::model::RequestBody;
+    use crate::api::verify::VerificationRequest;
+    use crate::model::HttpMethod;
+    use serde_json::{json, Value};
+    use std::collections::HashMap;
+
+    fn entry(method: HttpMethod, path: &str, body: &str) -> JournalEntry {
+        let request_body = RequestBody::SimpleRequestBody { raw_value: body.as_bytes().to_vec(), value: Some(body.to_string()), content_type: None };
+        JournalEntry::new(&method, path, &HashMap::new(), &json!({}), &request_body)
+    }
+
+    #[test]
+    fn count_matching_requests() {
+        let request: VerificationRequest = serde_json::from_value(json!({
+            "method": "POST",
+            "pathPattern": "/orders/\\d+",
+            "request": {"mode": "jlens", "headers": {}, "body": {"status": {"==": "new"}}}
+        })).unwrap();
+
+        let entries = vec![
+            entry(HttpMethod::Post, "/orders/1", r#"{"status": "new"}"#),
+            entry(HttpMethod::Post, "/orders/2", r#"{"status": "paid"}"#),
+            entry(HttpMethod::Get, "/orders/1", r#"{"status": "new"}"#),
+            entry(HttpMethod::Post, "/orders/3", r#"{"status": "new"}"#)
+        ];
+
+        let result = request.verify(entries);
+
+        assert_eq!(result.count, 2);
+        assert!(result.is_satisfied());
+    }
+
+    #[test]
+    fn report_near_misses_on_unexpected_count() {
+        let request: VerificationRequest = serde_json::from_value(json!({
+            "method": "POST",
+            "path": "/orders",
+            "request": {"mode": "json", "headers": {}, "body": {"id": 1}},
+            "times": 1
+        })).unwrap();
+
+        let result = request.verify(vec![entry(HttpMethod::Post, "/orders", r#"{"id": 2}"#)]);
+
+        assert!(!result.is_satisfied());
+        assert_eq!(result.near_misses.len(), 1);
+        assert_eq!(result.near_misses[0].mismatches, vec!["body".to_string()]);
+        assert_eq!(serde_json::to_value(&result).unwrap()["expected"], Value::from(1));
+    }
+}
@@ -0,0 +1,258 @@
+use crate::api::resolver::StubResolver;
+use crate::error::Error;
+use crate::model::persistent::HttpStub;
+use actix_web::rt::spawn;
+use log::{error, info};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::sleep;
+use uuid::Uuid;
+
+/// Loads stubs from the given files and directories, directories are scanned recursively for *.json files
+pub fn load_all(paths: &[PathBuf]) -> Result<Vec<HttpStub>, Error> {
+    // sources and ids are derived from the absolute path, however the path was given
+    let paths = paths.iter()
+        .map(|p| fs::canonicalize(p).map_err(|e| Error::new(format!("Can't read {}: {}", p.display(), e))))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut stubs = Vec::new();
+    let mut loaded = HashSet::new();
+
+    for file in collect_files(&paths)? {
+        // a file given both directly and within a directory is loaded once
+        let file = fs::canonicalize(&file).map_err(|e| Error::new(format!("Can't read {}: {}", file.display(), e)))?;
+
+        if loaded.insert(file.clone()) {
+            stubs.extend(load_stubs(&file)?);
+        }
+    }
+
+    let mut sources: HashMap<_, _> = HashMap::new();
+
+    for stub in stubs.iter() {
+        if let Some(other) = sources.insert(stub.id, stub.label()) {
+            return Err(Error::new(format!("Stubs {} and {} have the same id {}", other, stub.label(), stub.id)));
+        }
+    }
+
+    Ok(stubs)
+}
+
+pub fn load_stubs(path: &Path) -> Result<Vec<HttpStub>, Error> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| Error::new(format!("Can't read {}: {}", path.display(), e)))?;
+
+    let mut stubs = serde_json::from_str::<Vec<HttpStub>>(&contents)
+        .map_err(|e| Error::new(format!("Can't parse {}: {}", path.display(), e)))?;
+
+    let mut occurrences: HashMap<String, usize> = HashMap::new();
+
+    for stub in stubs.iter_mut() {
+        stub.validate().map_err(|e| Error::new(format!("{}: {}", path.display(), e)))?;
+        stub.source = Some(path.display().to_string());
+
+        // stubs without an explicit id keep the same one across reloads and restarts,
+        // it depends on the name rather than on the position within the file
+        if stub.id.is_nil() {
+            let occurrence = occurrences.entry(stub.name.clone()).and_modify(|n| *n += 1).or_insert(0);
+            let key = match occurrence {
+                0 => format!("{}#{}", path.display(), stub.name),
+                n => format!("{}#{}#{}", path.display(), stub.name, n)
+            };
+            stub.id = Uuid::new_v5(&Uuid::NAMESPACE_URL, key.as_bytes());
+        }
+    }
+
+    Ok(stubs)
+}
+
+/// Reloads file-defined stubs on SIGHUP and, if requested, whenever one of the files changes.
+/// Existing states are kept, only stubs whose seeded state is missing get seeded
+pub fn spawn_reloader(paths: &[PathBuf], stub_res: Arc<StubResolver>, watch: bool) -> Result<(), Error> {
+    let paths = paths.iter().map(fs::canonicalize).collect::<Result<Vec<_>, _>>().map_err(Error::from)?;
+    let (tx, mut rx) = mpsc::unbounded_channel::<()>();
+
+    let watcher = if watch { Some(watch_paths(&paths, tx.clone())?) } else { None };
+
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut hangups = signal(SignalKind::hangup()).map_err(Error::from)?;
+        let hup_tx = tx.clone();
+
+        spawn(async move {
+            while hangups.recv().await.is_some() {
+                info!("SIGHUP received, reloading stubs");
+
+                if hup_tx.send(()).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    drop(tx);
+
+    spawn(async move {
+        let _watcher = watcher;
+
+        while rx.recv().await.is_some() {
+            // editors tend to emit a burst of events on save
+            sleep(Duration::from_millis(200)).await;
+            while rx.try_recv().is_ok() {}
+
+            match load_all(&paths) {
+                Ok(stubs) => {
+                    info!("Reloaded {} stub(s)", stubs.len());
+                    stub_res.replace_file_stubs(stubs.clone()).await;
+
+                    for stub in stubs.iter() {
+                        stub_res.seed_state(stub).await;
+                    }
+                },
+                Err(err) => error!("Failed to reload stubs, keeping the previous ones: {}", err)
+            }
+        }
+    });
+
+    Ok(())
+}
+
+// ---- private stuff ----
+
+fn collect_files(paths: &[PathBuf]) -> Result<Vec<PathBuf>, Error> {
+    let mut files = Vec::new();
+
+    for path in paths {
+        if path.is_dir() {
+            let mut entries = fs::read_dir(path)
+                .and_then(|rd| rd.map(|e| e.map(|e| e.path())).collect::<Result<Vec<_>, _>>())
+                .map_err(|e| Error::new(format!("Can't read directory {}: {}", path.display(), e)))?;
+            entries.sort();
+
+            let nested = entries.into_iter().filter(|p| p.is_dir() || is_json(p)).collect::<Vec<_>>();
+            files.extend(collect_files(&nested)?);
+        } else {
+            files.push(path.clone());
+        }
+    }
+
+    Ok(files)
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "json")
+}
+
+fn watch_paths(paths: &[PathBuf], tx: mpsc::UnboundedSender<()>) -> Result<RecommendedWatcher, Error> {
+    let watched = paths.to_vec();
+
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<Event>| {
+        match res {
+            Ok(event) if !event.kind.is_access() && event.paths.iter().any(|p| is_watched(&watched, p)) => {
+                let _ = tx.send(());
+            },
+            Ok(_) => (),
+            Err(err) => error!("Watching mock files failed: {}", err)
+        }
+    }).map_err(Error::from)?;
+
+    for path in paths {
+        if path.is_dir() {
+            watcher.watch(path, RecursiveMode::Recursive).map_err(Error::from)?;
+        } else {
+            // watching the directory survives editors replacing the file on save
+            let dir = path.parent().map(PathBuf::from).unwrap_or(PathBuf::from("."));
+            watcher.watch(&dir, RecursiveMode::NonRecursive).map_err(Error::from)?;
+        }
+    }
+
+    Ok(watcher)
+}
+
+fn is_watched(watched: &[PathBuf], changed: &Path) -> bool {
+    watched.iter().any(|w| w == changed || (w.is_dir() && changed.starts_with(w) && is_json(changed)))
+}
+
+#[cfg(test)]
+mod loader_tests {
+    use crate::loader::load_all;
+    use crate::test_utils::{stub_json, temp_path};
+    use serde_json::json;
+    use std::fs;
+
+    fn mock_file(name: &str) -> String {
+        mock_file_with_method(name, "GET")
+    }
+
+    fn mock_file_with_method(name: &str, method: &str) -> String {
+        json!([stub_json(name, json!({"method": method}))]).to_string()
+    }
+
+    #[test]
+    fn load_stubs_from_directories_recursively() {
+        let root = temp_path("loader");
+        fs::create_dir_all(root.join("nested")).unwrap();
+        fs::write(root.join("a.json"), mock_file("a")).unwrap();
+        fs::write(root.join("nested").join("b.json"), mock_file("b")).unwrap();
+        fs::write(root.join("notes.txt"), "not a mock").unwrap();
+
+        let stubs = load_all(std::slice::from_ref(&root)).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(stubs.iter().map(|s| s.name.as_str()).collect::<Vec<_>>(), vec!["a", "b"]);
+        assert!(stubs[1].source.as_ref().is_some_and(|s| s.ends_with("b.json")));
+    }
+
+    #[test]
+    fn files_given_twice_are_loaded_once() {
+        let root = temp_path("loader");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("a.json"), mock_file("a")).unwrap();
+
+        let stubs = load_all(&[root.clone(), root.join("a.json"), root.join(".").join("a.json")]);
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(stubs.unwrap().len(), 1);
+    }
+
+    #[test]
+    fn method_typos_are_rejected() {
+        let root = temp_path("loader");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("typo.json"), mock_file_with_method("typo", "get")).unwrap();
+        fs::write(root.join("lowercase.json"), mock_file_with_method("lowercase", "purge")).unwrap();
+        fs::write(root.join("extension.json"), mock_file_with_method("extension", "PURGE")).unwrap();
+
+        let typo = load_all(&[root.join("typo.json")]);
+        let lowercase = load_all(&[root.join("lowercase.json")]);
+        let extension = load_all(&[root.join("extension.json")]);
+        fs::remove_dir_all(&root).unwrap();
+
+        assert!(typo.is_err());
+        assert!(lowercase.is_err());
+        assert_eq!(extension.unwrap()[0].method.as_str(), "PURGE");
+    }
+
+    #[test]
+    fn ids_do_not_depend_on_position_or_relative_path() {
+        let root = temp_path("loader");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("mocks.json"), mock_file("b")).unwrap();
+        let before = load_all(&[root.join("mocks.json")]).unwrap();
+
+        fs::write(root.join("mocks.json"), json!([stub_json("a", json!({})), stub_json("b", json!({}))]).to_string()).unwrap();
+        let after = load_all(&[root.join(".").join("mocks.json")]).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(after[1].name, "b");
+        assert_eq!(after[1].id, before[0].id);
+        assert_eq!(after[1].source, before[0].source);
+        assert_ne!(after[0].id, after[1].id);
+    }
+}
@@ -1,52 +1,78 @@
 extern crate clap;
 
 use crate::api::exec::ExecHandler;
+use crate::api::journal::RequestJournal;
+use crate::api::proxy::ProxyRoutes;
+use crate::api::recorder::Recorder;
 use crate::api::resolver::StubResolver;
-use crate::model::persistent::{HttpStub, State};
+use crate::storage::state::{InMemoryStateStorage, JsonFileStateStorage, StateStorage};
+use crate::storage::stub::{AppendOnlyStubStorage, StubStorage, VolatileStubStorage};
 use actix_web::{App, HttpServer};
-use actix_web::web::Data;
+use actix_web::rt::{spawn, time};
+use actix_web::web::{self, Data};
+use chrono::TimeDelta;
 use clap::Parser;
+use log::info;
 use simple_logger::SimpleLogger;
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::{BufReader, Read};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::RwLock;
-use uuid::Uuid;
 
 pub mod api;
 pub mod error;
+pub mod loader;
 pub mod misc;
 pub mod model;
 pub mod predicate_dsl;
 pub mod sanboxing;
+pub mod storage;
 pub mod utils;
 
+#[cfg(test)]
+mod test_utils;
+
+/// Longest lifetime chrono can represent
+const MAX_EPHEMERAL_TTL: u64 = (i64::MAX / 1000) as u64;
+
 #[derive(Parser, Debug)]
 #[clap(
     author = "Daniel Slapman <danslapman@gmail.com>",
     about = "Standalone mocking server"
 )]
 struct Args {
-    #[clap(help = "File containing mock configurations")]
-    mocks: String
+    #[clap(required_unless_present = "record", help = "Files or directories (scanned recursively for *.json) containing mock configurations")]
+    mocks: Vec<PathBuf>,
+    #[clap(long, help = "Reload mock configurations when any of the files changes")]
+    watch: bool,
+    #[clap(long, default_value_t = 1000, help = "Maximum number of requests kept in the request journal")]
+    journal_capacity: usize,
+    #[clap(long, default_value_t = 86400, value_parser = clap::value_parser!(u64).range(1..=MAX_EPHEMERAL_TTL), help = "Lifetime of ephemeral stubs in seconds")]
+    ephemeral_ttl: u64,
+    #[clap(long, env = "KOLIBRI_HOST", default_value = "0.0.0.0", help = "Address to bind to")]
+    host: String,
+    #[clap(long, env = "KOLIBRI_PORT", default_value_t = 8080, help = "Port to listen on, 0 picks a free port")]
+    port: u16,
+    #[clap(long, env = "KOLIBRI_EXEC_PREFIX", default_value = "/api/kolibri/exec", help = "Path prefix stubs are served under, / serves them at the root")]
+    exec_prefix: String,
+    #[clap(long, help = "JSON file to keep states in, states are kept in memory only if not set")]
+    state_file: Option<PathBuf>,
+    #[clap(long, help = "Log file to persist stubs created through the API, they are kept in memory only if not set")]
+    stub_log: Option<PathBuf>,
+    #[clap(long, env = "KOLIBRI_PROXY_URL", help = "Upstream URL requests without a matching stub are forwarded to")]
+    proxy_url: Option<String>,
+    #[clap(long = "proxy", value_name = "PREFIX=URL", help = "Upstream URL for requests without a matching stub under the given path prefix, may be repeated")]
+    proxies: Vec<String>,
+    #[clap(long, help = "Mock file to record requests forwarded to upstream into, existing stubs in it are kept")]
+    record: Option<PathBuf>,
+    #[clap(long, help = "Directory file responses are served from")]
+    resources_dir: Option<PathBuf>
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     let args = Args::parse();
 
-    let mock_file = File::open(args.mocks)?;
-    let mut mock_file_contents = "".to_string();
-    BufReader::new(mock_file).read_to_string(&mut mock_file_contents)?;
-
-    let mocks = serde_json::from_str::<Vec<HttpStub>>(&mock_file_contents)?;
-
-    let states: HashMap<Uuid, State> = HashMap::new();
-
-    let stub_resolver = StubResolver::new(mocks, RwLock::new(states));
-
-    let exec_handler = Data::new(ExecHandler::new(stub_resolver));
-
     SimpleLogger::new()
         .env()
         .with_level(log::LevelFilter::Debug)
@@ -54,13 +80,83 @@ async fn main() -> std::io::Result<()> {
         .init()
         .unwrap();
 
-    HttpServer::new(move || {
+    let stub_storage: Box<dyn StubStorage> = match &args.stub_log {
+        Some(path) => Box::new(AppendOnlyStubStorage::open(path.clone()).map_err(std::io::Error::other)?),
+        None => Box::new(VolatileStubStorage)
+    };
+
+    let mut mocks = loader::load_all(&args.mocks).map_err(std::io::Error::other)?;
+    mocks.extend(stub_storage.load().map_err(std::io::Error::other)?);
+
+    let states: Box<dyn StateStorage> = match args.state_file {
+        Some(path) => Box::new(JsonFileStateStorage::open(path).map_err(std::io::Error::other)?),
+        None => Box::new(InMemoryStateStorage::new())
+    };
+
+    let ephemeral_ttl = TimeDelta::seconds(args.ephemeral_ttl as i64);
+
+    let stub_resolver = Arc::new(StubResolver::new(RwLock::new(mocks), states, stub_storage, ephemeral_ttl));
+
+    for stub in stub_resolver.stubs().await.iter() {
+        stub_resolver.seed_state(stub).await;
+    }
+
+    loader::spawn_reloader(&args.mocks, stub_resolver.clone(), args.watch).map_err(std::io::Error::other)?;
+
+    let journal = Arc::new(RequestJournal::new(args.journal_capacity));
+    let proxy = ProxyRoutes::new(args.proxy_url, &args.proxies).map_err(std::io::Error::other)?;
+    if args.record.is_some() && proxy.is_empty() {
+        return Err(std::io::Error::other("--record needs an upstream set with --proxy-url or --proxy"));
+    }
+
+    let recorder = args.record.map(Recorder::open).transpose().map_err(std::io::Error::other)?;
+
+    let resources_dir = args.resources_dir.map(std::fs::canonicalize).transpose()?;
+
+    let exec_handler = Data::new(ExecHandler::new(stub_resolver.clone(), journal.clone(), proxy, recorder, resources_dir, &args.exec_prefix));
+    let stub_resolver = Data::from(stub_resolver);
+    let journal = Data::from(journal);
+
+    let purged_resolver = stub_resolver.clone().into_inner();
+    spawn(async move {
+        let mut interval = time::interval(Duration::from_secs(args.ephemeral_ttl.clamp(1, 60)));
+
+        loop {
+            interval.tick().await;
+
+            let purged = purged_resolver.purge_expired().await;
+            if purged > 0 {
+                info!("Purged {} expired ephemeral stub(s)", purged);
+            }
+        }
+    });
+
+    let server = HttpServer::new(move || {
         App::new()
             .app_data(exec_handler.clone())
-            .service(api::exec_get)
-            .service(api::exec_post)
+            .app_data(stub_resolver.clone())
+            .app_data(journal.clone())
+            .service(api::admin::list_stubs)
+            .service(api::admin::get_stub)
+            .service(api::admin::create_stub)
+            .service(api::admin::update_stub)
+            .service(api::admin::delete_stub)
+            .service(api::admin::list_states)
+            .service(api::admin::fetch_states)
+            .service(api::admin::insert_state)
+            .service(api::admin::delete_state)
+            .service(api::admin::delete_states)
+            .service(api::admin::list_journal)
+            .service(api::admin::find_in_journal)
+            .service(api::admin::clear_journal)
+            .service(api::admin::verify)
+            .default_service(web::to(api::exec))
     })
-        .bind(("0.0.0.0", 8080))?
-        .run()
-        .await
+        .bind((args.host, args.port))?;
+
+    for addr in server.addrs() {
+        info!("Listening on {}", addr);
+    }
+
+    server.run().await
 }
@@ -1,6 +1,6 @@
 use crate::predicate_dsl::keyword::Keyword;
 use crate::utils::js::optic::JsonOptic;
-use crate::utils::transformations::js::JsonTransformations;
+use crate::utils::transformations::js::{JsonTemplater, JsonTransformations};
 use serde_json::Value;
 use std::collections::HashMap;
 
@@ -15,6 +15,13 @@ impl Substitute<Value> for Value {
     }
 }
 
+impl Substitute<Value> for String {
+    fn substitute(&mut self, b: Value) -> &Self {
+        *self = JsonTemplater::new(b).interpolate(self);
+        self
+    }
+}
+
 pub trait Renderable {
     fn render_json(self) -> Value;
     fn fill<S: Clone>(&mut self, values: S) -> &Self where Value: Substitute<S>;
@@ -1,3 +1,4 @@
+use actix_web::http::Method;
 use serde::{Deserialize, Serialize};
 
 pub mod persistent;
@@ -21,5 +22,57 @@ pub enum HttpMethod {
     Options,
     Patch,
     Put,
-    Delete
-}
\ No newline at end of file
+    Delete,
+    Trace,
+    Connect,
+    #[serde(untagged)]
+    Extension(String)
+}
+
+impl HttpMethod {
+    pub fn as_str(&self) -> &str {
+        match self {
+            HttpMethod::Get => "GET",
+            HttpMethod::Post => "POST",
+            HttpMethod::Head => "HEAD",
+            HttpMethod::Options => "OPTIONS",
+            HttpMethod::Patch => "PATCH",
+            HttpMethod::Put => "PUT",
+            HttpMethod::Delete => "DELETE",
+            HttpMethod::Trace => "TRACE",
+            HttpMethod::Connect => "CONNECT",
+            HttpMethod::Extension(method) => method
+        }
+    }
+
+    /// Extension methods should be uppercase tokens distinct from the standard ones,
+    /// so a typo like "get" is not taken for an extension that never matches
+    pub fn is_valid(&self) -> bool {
+        match self {
+            HttpMethod::Extension(method) =>
+                !method.is_empty() &&
+                    method.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || "!#$%&'*+-.^_`|~".contains(c)) &&
+                    !STANDARD_METHODS.iter().any(|m| m.eq_ignore_ascii_case(method)),
+            _ => true
+        }
+    }
+}
+
+const STANDARD_METHODS: [&str; 9] = ["GET", "POST", "HEAD", "OPTIONS", "PATCH", "PUT", "DELETE", "TRACE", "CONNECT"];
+
+impl From<&Method> for HttpMethod {
+    fn from(method: &Method) -> Self {
+        match method.as_str() {
+            "GET" => HttpMethod::Get,
+            "POST" => HttpMethod::Post,
+            "HEAD" => HttpMethod::Head,
+            "OPTIONS" => HttpMethod::Options,
+            "PATCH" => HttpMethod::Patch,
+            "PUT" => HttpMethod::Put,
+            "DELETE" => HttpMethod::Delete,
+            "TRACE" => HttpMethod::Trace,
+            "CONNECT" => HttpMethod::Connect,
+            other => HttpMethod::Extension(other.to_string())
+        }
+    }
+}
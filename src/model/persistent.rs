@@ -1,13 +1,20 @@
-use crate::api::model::RequestBody;
+use crate::api::model::{form_to_json_value, RequestBody};
+use crate::api::multipart::Part;
+use crate::error::Error;
 use crate::misc::Substitute;
 use crate::model::*;
+use crate::predicate_dsl::binary::BinaryPredicate;
 use crate::predicate_dsl::json::JsonPredicate;
 use crate::predicate_dsl::keyword::Keyword;
+use crate::predicate_dsl::xpath::XPathPredicate;
 use crate::utils::js::optic::JsonOptic;
-use chrono::{DateTime, Utc};
+use crate::utils::transformations::js::JsonTemplater;
+use crate::utils::xml;
+use chrono::{DateTime, TimeDelta, Utc};
+use log::error;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
-use serde_json::{json, Value};
+use serde_json::{json, Map, Value};
 use std::collections::HashMap;
 use std::time::Duration;
 use uuid::Uuid;
@@ -41,6 +48,45 @@ pub enum HttpStubRequest {
         #[serde(default = "HashMap::new")]
         query: HashMap<JsonOptic, HashMap<Keyword, Value>>,
         body: JsonPredicate
+    },
+    #[serde(rename = "xml")]
+    XmlRequest {
+        headers: HashMap<String, String>,
+        #[serde(default = "HashMap::new")]
+        query: HashMap<JsonOptic, HashMap<Keyword, Value>>,
+        body: String
+    },
+    #[serde(rename = "xpath")]
+    XPathRequest {
+        headers: HashMap<String, String>,
+        #[serde(default = "HashMap::new")]
+        query: HashMap<JsonOptic, HashMap<Keyword, Value>>,
+        body: XPathPredicate,
+        #[serde(default = "HashMap::new")]
+        namespaces: HashMap<String, String>
+    },
+    /// application/x-www-form-urlencoded body, decoded into a JSON object, repeated fields become arrays
+    #[serde(rename = "form")]
+    FormRequest {
+        headers: HashMap<String, String>,
+        #[serde(default = "HashMap::new")]
+        query: HashMap<JsonOptic, HashMap<Keyword, Value>>,
+        body: JsonPredicate
+    },
+    #[serde(rename = "binary")]
+    BinaryRequest {
+        headers: HashMap<String, String>,
+        #[serde(default = "HashMap::new")]
+        query: HashMap<JsonOptic, HashMap<Keyword, Value>>,
+        body: BinaryPredicate
+    },
+    /// Each of `parts` should match at least one multipart/form-data part with the same name
+    #[serde(rename = "multipart")]
+    MultipartRequest {
+        headers: HashMap<String, String>,
+        #[serde(default = "HashMap::new")]
+        query: HashMap<JsonOptic, HashMap<Keyword, Value>>,
+        parts: HashMap<String, PartPattern>
     }
 }
 
@@ -67,19 +113,54 @@ impl HttpStubRequest {
             HttpStubRequest::JsonRequest { body, .. } =>
                 self.extract_json(r_body).map_or(false, |jx| &jx == body),
             HttpStubRequest::RawRequest { body, .. } =>
+                r_body.extract_string().is_some_and(|value| value == *body),
+            HttpStubRequest::JLensRequest { body, .. } | HttpStubRequest::FormRequest { body, .. } =>
+                self.extract_json(r_body).and_then(|jx| body.validate(jx).ok()).unwrap_or(false),
+            HttpStubRequest::XmlRequest { body, .. } =>
+                match r_body {
+                    RequestBody::SimpleRequestBody { value: Some(value), .. } => xml::same_document(value, body),
+                    _ => false
+                },
+            HttpStubRequest::XPathRequest { body, namespaces, .. } =>
                 match r_body {
-                    RequestBody::SimpleRequestBody { value, .. } => value == body,
+                    RequestBody::SimpleRequestBody { value: Some(value), .. } => body.validate(value, namespaces).unwrap_or(false),
                     _ => false
                 },
-            HttpStubRequest::JLensRequest { body, .. } =>
-                self.extract_json(r_body).and_then(|jx| body.validate(jx).ok()).unwrap_or(false)
+            HttpStubRequest::BinaryRequest { body, .. } =>
+                !matches!(r_body, RequestBody::AbsentRequestBody) && body.validate(r_body.raw_value(), r_body.content_type()),
+            HttpStubRequest::MultipartRequest { parts: patterns, .. } =>
+                match r_body {
+                    RequestBody::MultipartRequestBody { parts, .. } =>
+                        patterns.iter().all(|(name, pattern)| parts.iter().any(|p| p.name == *name && pattern.matches(p))),
+                    _ => false
+                }
         }
     }
 
     pub fn extract_json(&self, r_body: &RequestBody) -> Option<Value> {
         match (self, r_body) {
-            (HttpStubRequest::JsonRequest { .. } | HttpStubRequest::JLensRequest { .. }, RequestBody::SimpleRequestBody { value, .. }) =>
+            (HttpStubRequest::JsonRequest { .. } | HttpStubRequest::JLensRequest { .. }, RequestBody::SimpleRequestBody { value: Some(value), .. }) =>
                 serde_json::from_str(&value).ok(),
+            (HttpStubRequest::XmlRequest { .. } | HttpStubRequest::XPathRequest { .. }, RequestBody::SimpleRequestBody { value: Some(value), .. }) =>
+                xml::parse(value).ok().map(|package| xml::to_json(&package)),
+            (HttpStubRequest::FormRequest { .. }, RequestBody::SimpleRequestBody { value: Some(value), .. }) =>
+                form_to_json_value(value).ok(),
+            (HttpStubRequest::MultipartRequest { parts: patterns, .. }, RequestBody::MultipartRequestBody { parts, .. }) => {
+                let mut extracted = Map::new();
+
+                for part in parts {
+                    let value = patterns.get(&part.name).map_or_else(|| part.guess_json(), |pattern| pattern.extract_json(part));
+
+                    // repeated parts are collected into an array
+                    match extracted.get_mut(&part.name) {
+                        Some(Value::Array(values)) => values.push(value),
+                        Some(existing) => *existing = Value::Array(vec![existing.take(), value]),
+                        None => drop(extracted.insert(part.name.clone(), value))
+                    }
+                }
+
+                Some(json!({"parts": extracted}))
+            },
             _ => None
         }
     }
@@ -90,6 +171,11 @@ impl HttpStubRequest {
             HttpStubRequest::JsonRequest { headers, .. } => headers,
             HttpStubRequest::RawRequest { headers, .. } => headers,
             HttpStubRequest::JLensRequest { headers, .. } => headers,
+            HttpStubRequest::XmlRequest { headers, .. } => headers,
+            HttpStubRequest::XPathRequest { headers, .. } => headers,
+            HttpStubRequest::FormRequest { headers, .. } => headers,
+            HttpStubRequest::BinaryRequest { headers, .. } => headers,
+            HttpStubRequest::MultipartRequest { headers, .. } => headers,
         }
     }
 
@@ -99,6 +185,64 @@ impl HttpStubRequest {
             HttpStubRequest::JsonRequest { query, .. } => query,
             HttpStubRequest::RawRequest { query, .. } => query,
             HttpStubRequest::JLensRequest { query, .. } => query,
+            HttpStubRequest::XmlRequest { query, .. } => query,
+            HttpStubRequest::XPathRequest { query, .. } => query,
+            HttpStubRequest::FormRequest { query, .. } => query,
+            HttpStubRequest::BinaryRequest { query, .. } => query,
+            HttpStubRequest::MultipartRequest { query, .. } => query,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "mode")]
+pub enum PartPattern {
+    #[serde(rename = "raw")]
+    RawPart {
+        #[serde(default = "HashMap::new")]
+        headers: HashMap<String, String>,
+        body: String
+    },
+    #[serde(rename = "json")]
+    JsonPart {
+        #[serde(default = "HashMap::new")]
+        headers: HashMap<String, String>,
+        body: Value
+    },
+    #[serde(rename = "jlens")]
+    JLensPart {
+        #[serde(default = "HashMap::new")]
+        headers: HashMap<String, String>,
+        body: JsonPredicate
+    }
+}
+
+impl PartPattern {
+    pub fn matches(&self, part: &Part) -> bool {
+        let headers_match = self.headers().iter()
+            .all(|(k, v)| part.headers.get(&k.to_lowercase()).is_some_and(|vx| vx.to_lowercase() == v.to_lowercase()));
+
+        headers_match && match self {
+            PartPattern::RawPart { body, .. } => part.extract_string().is_some_and(|value| value == *body),
+            PartPattern::JsonPart { body, .. } => self.extract_json(part) == *body,
+            PartPattern::JLensPart { body, .. } => body.validate(self.extract_json(part)).unwrap_or(false)
+        }
+    }
+
+    pub fn extract_json(&self, part: &Part) -> Value {
+        match self {
+            PartPattern::RawPart { .. } =>
+                part.extract_string().map(Value::String).unwrap_or(Value::Null),
+            PartPattern::JsonPart { .. } | PartPattern::JLensPart { .. } =>
+                part.extract_string().and_then(|value| serde_json::from_str(&value).ok()).unwrap_or(Value::Null)
+        }
+    }
+
+    fn headers(&self) -> &HashMap<String, String> {
+        match self {
+            PartPattern::RawPart { headers, .. } => headers,
+            PartPattern::JsonPart { headers, .. } => headers,
+            PartPattern::JLensPart { headers, .. } => headers
         }
     }
 }
@@ -106,51 +250,169 @@ impl HttpStubRequest {
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(tag = "mode")]
 pub enum HttpStubResponse {
+    /// Body is served verbatim unless `template` is set
     #[serde(rename = "raw")]
     RawResponse {
-        code: u16,
+        code: ResponseCode,
         headers: HashMap<String, String>,
         body: String,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        template: bool,
         #[serde(skip_serializing_if = "Option::is_none")]
         delay: Option<Duration>
     },
     #[serde(rename = "json")]
     JsonResponse {
-        code: u16,
+        code: ResponseCode,
         headers: HashMap<String, String>,
         body: Value,
         #[serde(skip_serializing_if = "Option::is_none")]
         delay: Option<Duration>,
         //is_template: bool
+    },
+    /// Body is an XML template, substituted values are escaped
+    #[serde(rename = "xml")]
+    XmlResponse {
+        code: ResponseCode,
+        headers: HashMap<String, String>,
+        body: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        delay: Option<Duration>
+    },
+    #[serde(rename = "binary")]
+    BinaryResponse {
+        code: ResponseCode,
+        headers: HashMap<String, String>,
+        #[serde(with = "base64_body")]
+        body: Vec<u8>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        delay: Option<Duration>
+    },
+    /// Serves a file from the resources directory, `path` is a template relative to it
+    #[serde(rename = "file")]
+    FileResponse {
+        code: ResponseCode,
+        headers: HashMap<String, String>,
+        path: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        delay: Option<Duration>
+    },
+    /// Forwards the request to `uri` and responds with the upstream JSON, patched with templated values
+    #[serde(rename = "proxy")]
+    ProxyResponse {
+        uri: String,
+        #[serde(default)]
+        patch: HashMap<JsonOptic, String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        delay: Option<Duration>
     }
 }
 
 impl HttpStubResponse {
+    pub fn get_code(&self) -> u16 {
+        match self {
+            HttpStubResponse::RawResponse { code, .. } => code.as_u16(),
+            HttpStubResponse::JsonResponse { code, .. } => code.as_u16(),
+            HttpStubResponse::XmlResponse { code, .. } => code.as_u16(),
+            HttpStubResponse::BinaryResponse { code, .. } => code.as_u16(),
+            HttpStubResponse::FileResponse { code, .. } => code.as_u16(),
+            // the actual code is known only once the upstream has responded
+            HttpStubResponse::ProxyResponse { .. } => 502
+        }
+    }
+
     pub fn get_delay(&self) -> &Option<Duration> {
         match self {
             HttpStubResponse::RawResponse { delay, .. } => delay,
-            HttpStubResponse::JsonResponse { delay, .. } => delay
+            HttpStubResponse::JsonResponse { delay, .. } => delay,
+            HttpStubResponse::XmlResponse { delay, .. } => delay,
+            HttpStubResponse::BinaryResponse { delay, .. } => delay,
+            HttpStubResponse::FileResponse { delay, .. } => delay,
+            HttpStubResponse::ProxyResponse { delay, .. } => delay
+        }
+    }
+
+    fn code_and_headers_mut(&mut self) -> Option<(&mut ResponseCode, &mut HashMap<String, String>)> {
+        match self {
+            HttpStubResponse::RawResponse { code, headers, .. } => Some((code, headers)),
+            HttpStubResponse::JsonResponse { code, headers, .. } => Some((code, headers)),
+            HttpStubResponse::XmlResponse { code, headers, .. } => Some((code, headers)),
+            HttpStubResponse::BinaryResponse { code, headers, .. } => Some((code, headers)),
+            HttpStubResponse::FileResponse { code, headers, .. } => Some((code, headers)),
+            HttpStubResponse::ProxyResponse { .. } => None
         }
     }
 }
 
 impl Substitute<Value> for HttpStubResponse {
     fn substitute(&mut self, b: Value) -> &Self {
-        
+        // A templater starts a JS runtime, so it is only built once a placeholder is met
+        let mut templater = None;
+
         match self {
-            HttpStubResponse::JsonResponse { body, .. } =>
-                drop(body.substitute(b)),
+            HttpStubResponse::RawResponse { body, template: true, .. } if JsonTemplater::is_template(body) =>
+                *body = templater_of(&mut templater, &b).interpolate(body),
+            HttpStubResponse::JsonResponse { body, .. } if JsonTemplater::has_templates(body) =>
+                templater_of(&mut templater, &b).substitute_in_place(body),
+            HttpStubResponse::XmlResponse { body, .. } if JsonTemplater::is_template(body) =>
+                *body = templater_of(&mut templater, &b).interpolate_escaped(body, xml::escape),
+            HttpStubResponse::FileResponse { path, .. } if JsonTemplater::is_template(path) =>
+                *path = templater_of(&mut templater, &b).interpolate(path),
+            HttpStubResponse::ProxyResponse { uri, .. } if JsonTemplater::is_template(uri) =>
+                *uri = templater_of(&mut templater, &b).interpolate(uri),
             _ => ()
         }
 
+        if let Some((code, headers)) = self.code_and_headers_mut() {
+            code.render(&mut templater, &b);
+
+            for value in headers.values_mut().filter(|v| JsonTemplater::is_template(v)) {
+                *value = templater_of(&mut templater, &b).interpolate(value);
+            }
+        }
+
         self
     }
 }
 
+/// Status code of a stub response, either a number or a template rendering into one, e.g. `"${state.status}"`
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum ResponseCode {
+    Code(u16),
+    Template(String)
+}
+
+impl ResponseCode {
+    /// Templates which didn't render into a valid code give 500
+    pub fn as_u16(&self) -> u16 {
+        match self {
+            ResponseCode::Code(code) => *code,
+            ResponseCode::Template(_) => 500
+        }
+    }
+
+    fn render(&mut self, templater: &mut Option<JsonTemplater>, values: &Value) {
+        if let ResponseCode::Template(template) = self {
+            let rendered = match JsonTemplater::is_template(template) {
+                true => templater_of(templater, values).interpolate(template),
+                false => template.clone()
+            };
+
+            match rendered.trim().parse::<u16>() {
+                Ok(code) if is_valid_code(code) => *self = ResponseCode::Code(code),
+                _ => error!("Status code template {:?} rendered into {:?}, which is not a status code", template, rendered)
+            }
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct HttpStub {
-    #[serde(default)]
+    #[serde(default = "Uuid::nil")]
+    pub id: Uuid,
+    #[serde(default = "Utc::now")]
     pub created: DateTime<Utc>,
     pub scope: Scope,
     #[serde(default)]
@@ -171,10 +433,61 @@ pub struct HttpStub {
     pub persist: Option<HashMap<JsonOptic, Value>>,
     pub response: HttpStubResponse,
     #[serde(default)]
-    pub callback: Option<Callback>
+    pub callback: Option<Callback>,
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>
 }
 
 impl HttpStub {
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.path.is_none() && self.path_pattern.is_none() {
+            return Err(Error::new(format!("Stub {:?} should have either path or pathPattern", self.name)));
+        }
+
+        if !self.method.is_valid() {
+            return Err(Error::new(format!("Stub {:?} has invalid method {:?}, methods are uppercase", self.name, self.method.as_str())));
+        }
+
+        if self.scope == Scope::Countdown && self.times.is_none() {
+            return Err(Error::new(format!("Countdown stub {:?} should have times set", self.name)));
+        }
+
+        if self.seed.as_ref().is_some_and(|seed| !seed.is_object()) {
+            return Err(Error::new(format!("Seed of stub {:?} should be a JSON object", self.name)));
+        }
+
+        if let HttpStubRequest::XmlRequest { body, .. } = &self.request {
+            xml::parse(body).map_err(|e| Error::new(format!("Request body of stub {:?} is not valid: {}", self.name, e)))?;
+        }
+
+        if !is_valid_code(self.response.get_code()) {
+            return Err(Error::new(format!("Stub {:?} has invalid status code {}", self.name, self.response.get_code())));
+        }
+
+        if let HttpStubResponse::XmlResponse { body, .. } = &self.response {
+            xml::parse(body).map_err(|e| Error::new(format!("Response body of stub {:?} is not valid: {}", self.name, e)))?;
+        }
+
+        Ok(())
+    }
+
+    /// Human-readable reference to the stub including where it came from
+    pub fn label(&self) -> String {
+        match &self.source {
+            Some(source) => format!("{:?} ({})", self.name, source),
+            None => format!("{:?} ({})", self.name, self.id)
+        }
+    }
+
+    pub fn is_stateful(&self) -> bool {
+        self.state.is_some() || self.persist.is_some()
+    }
+
+    pub fn is_expired(&self, ttl: TimeDelta, now: DateTime<Utc>) -> bool {
+        // a lifetime reaching past the representable dates never ends
+        self.scope == Scope::Ephemeral && self.created.checked_add_signed(ttl).is_some_and(|t| t <= now)
+    }
+
     pub fn extract_groups(&self, path: &str) -> Option<HashMap<String, String>> {
         self.path_pattern.clone().and_then(|pattern| {
             let names = pattern.capture_names().filter_map(|n| n); 
@@ -235,7 +548,7 @@ pub enum Callback {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct State {
     pub id: Uuid,
     pub created: DateTime<Utc>,
@@ -250,4 +563,77 @@ impl State {
             data: json!({})
         }
     }
-}
\ No newline at end of file
+}
+
+fn templater_of<'a>(templater: &'a mut Option<JsonTemplater>, values: &Value) -> &'a mut JsonTemplater {
+    templater.get_or_insert_with(|| JsonTemplater::new(values.clone()))
+}
+
+/// Same range actix accepts
+fn is_valid_code(code: u16) -> bool {
+    (100..1000).contains(&code)
+}
+
+mod base64_body {
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        STANDARD.decode(encoded.as_bytes()).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod persistent_tests {
+    use crate::api::model::RequestBody;
+    use crate::model::persistent::HttpStubRequest;
+    use crate::test_utils::stub;
+    use chrono::{TimeDelta, Utc};
+    use serde_json::json;
+
+    fn form_body(encoded: &str) -> RequestBody {
+        RequestBody::SimpleRequestBody {
+            raw_value: encoded.as_bytes().to_vec(),
+            value: Some(encoded.to_string()),
+            content_type: Some("application/x-www-form-urlencoded".to_string())
+        }
+    }
+
+    #[test]
+    fn form_request_should_decode_typed_and_repeated_fields() {
+        let request = serde_json::from_value::<HttpStubRequest>(json!({
+            "mode": "form",
+            "headers": {},
+            "body": {
+                "name": {"==": "Peka Kekovsky"},
+                "age": {">": 18},
+                "tag": {"==": ["a", "b"]}
+            }
+        })).unwrap();
+
+        let body = form_body("name=Peka+Kekovsky&age=42&tag=a&tag=b&agree=true");
+
+        assert_eq!(request.extract_json(&body), Some(json!({"name": "Peka Kekovsky", "age": 42, "tag": ["a", "b"], "agree": true})));
+        assert!(request.check_body(&body));
+        assert!(!request.check_body(&form_body("name=Peka+Kekovsky&age=17&tag=a&tag=b")));
+        assert!(!request.check_body(&form_body("name=Peka+Kekovsky&age=42&tag=b")));
+    }
+
+    #[test]
+    fn only_ephemeral_stubs_expire() {
+        let now = Utc::now();
+        let ephemeral = stub("ephemeral", json!({"scope": "ephemeral", "created": now - TimeDelta::seconds(10)}));
+        let persistent = stub("persistent", json!({"created": now - TimeDelta::seconds(10)}));
+
+        assert!(ephemeral.is_expired(TimeDelta::seconds(10), now));
+        assert!(!ephemeral.is_expired(TimeDelta::seconds(11), now));
+        assert!(!ephemeral.is_expired(TimeDelta::MAX, now));
+        assert!(!persistent.is_expired(TimeDelta::seconds(1), now));
+    }
+}
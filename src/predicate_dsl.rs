@@ -1,2 +1,4 @@
+pub mod binary;
 pub mod json;
-pub mod keyword;
\ No newline at end of file
+pub mod keyword;
+pub mod xpath;
\ No newline at end of file
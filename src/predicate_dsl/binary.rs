@@ -0,0 +1,121 @@
+use crate::predicate_dsl::json::{check_conditions, validate_condition};
+use crate::predicate_dsl::keyword::Keyword;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+/// Conditions on a body that isn't necessarily text, all of the specified ones should hold
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(try_from = "BinaryPredicateSpec", into = "BinaryPredicateSpec")]
+pub struct BinaryPredicate {
+    content: Option<Vec<u8>>,
+    size: Option<HashMap<Keyword, Value>>,
+    sha256: Option<String>,
+    content_type: Option<String>
+}
+
+impl BinaryPredicate {
+    /// Matches exactly the given content
+    pub fn of_content(content: Vec<u8>) -> BinaryPredicate {
+        BinaryPredicate { content: Some(content), size: None, sha256: None, content_type: None }
+    }
+
+    pub fn validate(&self, bytes: &[u8], content_type: Option<&str>) -> bool {
+        self.content.as_ref().is_none_or(|content| content == bytes) &&
+            self.size.as_ref().is_none_or(|conds| check_conditions([(conds, Value::from(bytes.len()))].into_iter()).unwrap_or(false)) &&
+            self.sha256.as_ref().is_none_or(|digest| *digest == sha256_hex(bytes)) &&
+            self.content_type.as_ref().is_none_or(|expected| content_type.is_some_and(|ct| essence(ct) == essence(expected)))
+    }
+}
+
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// ---- private stuff ----
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BinaryPredicateSpec {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    base64: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    size: Option<HashMap<Keyword, Value>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sha256: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    content_type: Option<String>
+}
+
+impl TryFrom<BinaryPredicateSpec> for BinaryPredicate {
+    type Error = String;
+
+    fn try_from(spec: BinaryPredicateSpec) -> Result<Self, Self::Error> {
+        let content = spec.base64.map(|b64| STANDARD.decode(b64.as_bytes()))
+            .transpose()
+            .map_err(|e| format!("base64 is malformed: {}", e))?;
+
+        if spec.size.as_ref().is_some_and(|conds| conds.iter().any(|(kwd, v)| !validate_condition(kwd, v))) {
+            return Err("Conditions are faulty on size".to_string());
+        }
+
+        if spec.sha256.as_ref().is_some_and(|digest| digest.len() != 64 || !digest.chars().all(|c| c.is_ascii_hexdigit())) {
+            return Err("sha256 should be 64 hexadecimal digits".to_string());
+        }
+
+        Ok(BinaryPredicate {
+            content,
+            size: spec.size,
+            sha256: spec.sha256.map(|digest| digest.to_lowercase()),
+            content_type: spec.content_type
+        })
+    }
+}
+
+impl From<BinaryPredicate> for BinaryPredicateSpec {
+    fn from(predicate: BinaryPredicate) -> Self {
+        BinaryPredicateSpec {
+            base64: predicate.content.map(|content| STANDARD.encode(content)),
+            size: predicate.size,
+            sha256: predicate.sha256,
+            content_type: predicate.content_type
+        }
+    }
+}
+
+/// Content type without parameters
+fn essence(content_type: &str) -> String {
+    content_type.split(';').next().unwrap_or_default().trim().to_lowercase()
+}
+
+#[cfg(test)]
+mod binary_tests {
+    use crate::predicate_dsl::binary::{sha256_hex, BinaryPredicate};
+    use serde_json::json;
+
+    const PAYLOAD: &[u8] = &[0x00, 0xff, 0x10];
+
+    #[test]
+    fn binary_predicate_should_check_all_conditions() {
+        let predicate = serde_json::from_value::<BinaryPredicate>(json!({
+            "base64": "AP8Q",
+            "size": {"<": 4},
+            "sha256": sha256_hex(PAYLOAD).to_uppercase(),
+            "contentType": "application/octet-stream"
+        })).unwrap();
+
+        assert!(predicate.validate(PAYLOAD, Some("Application/Octet-Stream; foo=bar")));
+        assert!(!predicate.validate(PAYLOAD, Some("image/png")));
+        assert!(!predicate.validate(&[0x00], Some("application/octet-stream")));
+    }
+
+    #[test]
+    fn binary_predicate_should_reject_poor_specification() {
+        assert!(serde_json::from_value::<BinaryPredicate>(json!({"base64": "%%%"})).is_err());
+        assert!(serde_json::from_value::<BinaryPredicate>(json!({"size": {"<": "big"}})).is_err());
+        assert!(serde_json::from_value::<BinaryPredicate>(json!({"sha256": "abc"})).is_err());
+    }
+}
@@ -18,31 +18,10 @@ pub struct JsonPredicate {
 
 impl JsonPredicate {
     pub fn validate(&self, json: Value) -> Result<bool, PredicateConstructionError<'_>> {
-        let mut result: Vec<Result<bool, ValidationError<'_>>> = vec![];
-
-        for (jo, conds) in self.definition.iter() {
+        check_conditions(self.definition.iter().map(|(jo, conds)| {
             let all_data = json.get_all(jo);
-            let data = all_data.first().unwrap_or(&&Value::Null);
-
-            for (kwd, etalon) in conds.iter() {
-                result.push(JsonPredicate::validate_one(kwd, etalon, *data));
-            }
-        }
-
-        let (oks, errs): (Vec<_>, Vec<_>) = result.into_iter().partition(|el| el.is_ok());
-
-        if errs.len() == 0 {
-            Ok(oks.into_iter().filter_map(|el| el.ok()).all(|el| el))
-        } else if errs.iter().all(|err| err.as_ref().err().unwrap().is_data_error()) {
-            Ok(false)
-        } else {
-            let condition_errors = errs.into_iter().filter_map(|el| el.err())
-                .filter_map(|err| match err {
-                    ValidationError::ConditionError { keyword, argument } => Some((keyword, argument)),
-                    _ => None
-                }).collect::<Vec<_>>();
-            Err(PredicateConstructionError { problems: condition_errors })
-        }
+            (conds, all_data.first().map(|v| (*v).clone()).unwrap_or(Value::Null))
+        }))
     }
 
     pub fn from_spec(spec: Spec) -> JsonPredicate {
@@ -120,7 +99,33 @@ impl Debug for JsonPredicate {
     }
 }
 
-fn validate_condition<'r>(kwd: &'r Keyword, etalon: &'r Value) -> bool {
+/// Checks conditions against the values they are bound to, so predicates over other kinds of documents can share keyword semantics
+pub fn check_conditions<'r>(bound: impl Iterator<Item = (&'r HashMap<Keyword, Value>, Value)>) -> Result<bool, PredicateConstructionError<'r>> {
+    let mut result: Vec<Result<bool, ValidationError<'r>>> = vec![];
+
+    for (conds, data) in bound {
+        for (kwd, etalon) in conds.iter() {
+            result.push(JsonPredicate::validate_one(kwd, etalon, &data));
+        }
+    }
+
+    let (oks, errs): (Vec<_>, Vec<_>) = result.into_iter().partition(|el| el.is_ok());
+
+    if errs.is_empty() {
+        Ok(oks.into_iter().filter_map(|el| el.ok()).all(|el| el))
+    } else if errs.iter().all(|err| err.as_ref().err().unwrap().is_data_error()) {
+        Ok(false)
+    } else {
+        let condition_errors = errs.into_iter().filter_map(|el| el.err())
+            .filter_map(|err| match err {
+                ValidationError::ConditionError { keyword, argument } => Some((keyword, argument)),
+                _ => None
+            }).collect::<Vec<_>>();
+        Err(PredicateConstructionError { problems: condition_errors })
+    }
+}
+
+pub fn validate_condition<'r>(kwd: &'r Keyword, etalon: &'r Value) -> bool {
     match (kwd, etalon) {
         (Keyword::Equals | Keyword::NotEq, _) => true,
         (Keyword::Greater | Keyword::Gte | Keyword::Less | Keyword::Lte, Value::Number(_)) => true,
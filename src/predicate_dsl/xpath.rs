@@ -0,0 +1,123 @@
+use crate::predicate_dsl::json::{check_conditions, validate_condition, PredicateConstructionError};
+use crate::predicate_dsl::keyword::Keyword;
+use crate::utils::xml;
+use serde::{Serialize, Deserialize, Serializer, Deserializer};
+use serde::de::Error;
+use serde_json::{Number, Value};
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use sxd_document::dom::Document;
+use sxd_xpath::{Context, Factory};
+
+type Spec = HashMap<String, HashMap<Keyword, Value>>;
+
+/// Same conditions as `JsonPredicate` has, but applied to values selected by XPath expressions
+#[derive(Clone)]
+pub struct XPathPredicate {
+    definition: Spec
+}
+
+impl XPathPredicate {
+    /// `namespaces` binds prefixes used in expressions to namespace URIs
+    pub fn validate(&self, xml: &str, namespaces: &HashMap<String, String>) -> Result<bool, PredicateConstructionError<'_>> {
+        let Ok(package) = xml::parse(xml) else {
+            return Ok(false);
+        };
+        let document = package.as_document();
+
+        let mut context = Context::new();
+        for (prefix, uri) in namespaces {
+            context.set_namespace(prefix, uri);
+        }
+
+        check_conditions(self.definition.iter().map(|(xpath, conds)| (conds, evaluate(&document, &context, xpath))))
+    }
+}
+
+impl Serialize for XPathPredicate {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+        self.definition.serialize(serializer)
+    }
+}
+
+impl <'de> Deserialize<'de> for XPathPredicate {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
+        let spec = Spec::deserialize(deserializer)?;
+        let factory = Factory::new();
+
+        let faulty_fields = spec.iter()
+            .filter(|(xpath, cond)| !matches!(factory.build(xpath), Ok(Some(_))) || cond.iter().any(|(kwd, v)| !validate_condition(kwd, v)))
+            .map(|(xpath, _)| xpath.clone())
+            .collect::<Vec<_>>();
+
+        if !faulty_fields.is_empty() {
+            Err(D::Error::custom(format!("Conditions are faulty on expressions: {}", faulty_fields.join(", "))))
+        } else {
+            Ok(XPathPredicate { definition: spec })
+        }
+    }
+}
+
+impl Debug for XPathPredicate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            serde_json::to_string(&self.definition).expect("Unserializable XPathPredicate!")
+        )
+    }
+}
+
+/// Empty node sets become null and node sets of several nodes become arrays
+fn evaluate(document: &Document, context: &Context, xpath: &str) -> Value {
+    let result = Factory::new().build(xpath).ok().flatten()
+        .and_then(|compiled| compiled.evaluate(context, document.root()).ok());
+
+    match result {
+        Some(sxd_xpath::Value::Boolean(b)) => Value::Bool(b),
+        Some(sxd_xpath::Value::Number(n)) if n.fract() == 0.0 && n.abs() < i64::MAX as f64 => Value::from(n as i64),
+        Some(sxd_xpath::Value::Number(n)) => Number::from_f64(n).map(Value::Number).unwrap_or(Value::Null),
+        Some(sxd_xpath::Value::String(s)) => xml::typed(&s),
+        Some(sxd_xpath::Value::Nodeset(nodes)) => {
+            let mut values = nodes.document_order().into_iter().map(|n| xml::typed(&n.string_value())).collect::<Vec<_>>();
+
+            match values.len() {
+                0 => Value::Null,
+                1 => values.remove(0),
+                _ => Value::Array(values)
+            }
+        },
+        None => Value::Null
+    }
+}
+
+#[cfg(test)]
+mod xpath_tests {
+    use crate::predicate_dsl::xpath::XPathPredicate;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    const ORDER: &str = r#"<o:order xmlns:o="urn:orders" id="42"><o:item>apple</o:item><o:item>pear</o:item></o:order>"#;
+
+    #[test]
+    fn xpath_predicate_should_check_selected_values() {
+        let predicate = serde_json::from_value::<XPathPredicate>(json!({
+            "/o:order/@id": {"==": 42},
+            "count(//o:item)": {">": 1},
+            "//o:item": {"&[_]": ["apple"]},
+            "//o:missing": {"exists": false}
+        })).unwrap();
+        let namespaces = HashMap::from([("o".to_string(), "urn:orders".to_string())]);
+
+        assert!(predicate.validate(ORDER, &namespaces).unwrap_or(false));
+        assert!(!predicate.validate("<order/>", &namespaces).unwrap_or(false));
+        assert!(!predicate.validate("not xml", &namespaces).unwrap_or(false));
+    }
+
+    #[test]
+    fn xpath_predicate_should_reject_malformed_expressions() {
+        let predicate = serde_json::from_value::<XPathPredicate>(json!({"//item[": {"==": 1}}));
+
+        assert_eq!(predicate.err().unwrap().to_string(), "Conditions are faulty on expressions: //item[");
+    }
+}
@@ -16,8 +16,10 @@ fn deno_eval<'s, T>(scope: &mut v8::HandleScope<'s>, code: &str,) -> Option<v8::
         v8::Local<'s, T>: TryFrom<v8::Local<'s, v8::Value>>,
 {
     let scope = &mut v8::EscapableHandleScope::new(scope);
-    let source = v8::String::new(scope, code).unwrap();
-    let script = v8::Script::compile(scope, source, None).unwrap();
+    // swallows exceptions, both syntax and runtime errors just give None
+    let scope = &mut v8::TryCatch::new(scope);
+    let source = v8::String::new(scope, code)?;
+    let script = v8::Script::compile(scope, source, None)?;
     let v = script.run(scope)?;
     scope.escape(v).try_into().ok()
 }
@@ -0,0 +1,43 @@
+use crate::error::Error;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub mod state;
+pub mod stub;
+
+/// Writes into a sibling temporary file first, so a crash never leaves a truncated file behind
+fn write_atomically(path: &Path, contents: &[u8]) -> Result<(), Error> {
+    let tmp_path = tmp_path_of(path);
+
+    fs::write(&tmp_path, contents).map_err(Error::from)?;
+    fs::rename(&tmp_path, path).map_err(Error::from)
+}
+
+pub fn write_json_atomically<T: Serialize + ?Sized>(path: &Path, value: &T) -> Result<(), Error> {
+    let contents = serde_json::to_vec_pretty(value).map_err(Error::from)?;
+
+    write_atomically(path, &contents)
+}
+
+// ---- private stuff ----
+
+/// The whole file name is kept, so `mocks.json` and `mocks.state` get different temporary files
+fn tmp_path_of(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".tmp");
+
+    path.with_file_name(file_name)
+}
+
+#[cfg(test)]
+mod storage_tests {
+    use crate::storage::tmp_path_of;
+    use std::path::Path;
+
+    #[test]
+    fn temporary_files_do_not_clash() {
+        assert_eq!(tmp_path_of(Path::new("data/mocks.json")), Path::new("data/mocks.json.tmp"));
+        assert_ne!(tmp_path_of(Path::new("mocks.json")), tmp_path_of(Path::new("mocks.state")));
+    }
+}
@@ -0,0 +1,178 @@
+use crate::error::Error;
+use crate::model::persistent::State;
+use crate::predicate_dsl::json::JsonPredicate;
+use crate::storage::write_json_atomically;
+use log::error;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::RwLock;
+use uuid::Uuid;
+
+pub trait StateStorage: Send + Sync {
+    fn all(&self) -> Vec<State>;
+    fn get(&self, id: &Uuid) -> Option<State>;
+    fn find(&self, predicate: &JsonPredicate) -> Vec<State>;
+    fn upsert(&self, state: State) -> Result<(), Error>;
+    fn remove(&self, id: &Uuid) -> Result<bool, Error>;
+    fn remove_matching(&self, predicate: &JsonPredicate) -> Result<usize, Error>;
+}
+
+pub struct InMemoryStateStorage {
+    states: RwLock<HashMap<Uuid, State>>
+}
+
+impl InMemoryStateStorage {
+    pub fn new() -> InMemoryStateStorage {
+        InMemoryStateStorage { states: RwLock::new(HashMap::new()) }
+    }
+}
+
+impl Default for InMemoryStateStorage {
+    fn default() -> Self {
+        InMemoryStateStorage::new()
+    }
+}
+
+impl StateStorage for InMemoryStateStorage {
+    fn all(&self) -> Vec<State> {
+        self.states.read().unwrap().values().cloned().collect()
+    }
+
+    fn get(&self, id: &Uuid) -> Option<State> {
+        self.states.read().unwrap().get(id).cloned()
+    }
+
+    fn find(&self, predicate: &JsonPredicate) -> Vec<State> {
+        self.states.read().unwrap().values().filter(|s| state_matches(predicate, s)).cloned().collect()
+    }
+
+    fn upsert(&self, state: State) -> Result<(), Error> {
+        self.states.write().unwrap().insert(state.id, state);
+        Ok(())
+    }
+
+    fn remove(&self, id: &Uuid) -> Result<bool, Error> {
+        Ok(self.states.write().unwrap().remove(id).is_some())
+    }
+
+    fn remove_matching(&self, predicate: &JsonPredicate) -> Result<usize, Error> {
+        let mut states = self.states.write().unwrap();
+        let count_before = states.len();
+        states.retain(|_, s| !state_matches(predicate, s));
+        Ok(count_before - states.len())
+    }
+}
+
+/// Keeps all states in memory and rewrites the whole JSON file on every change.
+/// The write blocks the calling worker while holding the lock, which is fine for the small state sets of a mock server
+pub struct JsonFileStateStorage {
+    path: PathBuf,
+    states: RwLock<HashMap<Uuid, State>>
+}
+
+impl JsonFileStateStorage {
+    pub fn open(path: PathBuf) -> Result<JsonFileStateStorage, Error> {
+        let states = if path.exists() {
+            let contents = fs::read_to_string(&path).map_err(Error::from)?;
+            serde_json::from_str::<Vec<State>>(&contents)
+                .map_err(|e| Error::new(format!("Can't parse state file {}: {}", path.display(), e)))?
+        } else {
+            vec![]
+        };
+
+        Ok(JsonFileStateStorage {
+            path,
+            states: RwLock::new(states.into_iter().map(|s| (s.id, s)).collect())
+        })
+    }
+
+    fn flush(&self, states: &HashMap<Uuid, State>) -> Result<(), Error> {
+        let mut snapshot = states.values().collect::<Vec<_>>();
+        snapshot.sort_by_key(|s| (s.created, s.id));
+
+        write_json_atomically(&self.path, &snapshot)
+    }
+}
+
+impl StateStorage for JsonFileStateStorage {
+    fn all(&self) -> Vec<State> {
+        self.states.read().unwrap().values().cloned().collect()
+    }
+
+    fn get(&self, id: &Uuid) -> Option<State> {
+        self.states.read().unwrap().get(id).cloned()
+    }
+
+    fn find(&self, predicate: &JsonPredicate) -> Vec<State> {
+        self.states.read().unwrap().values().filter(|s| state_matches(predicate, s)).cloned().collect()
+    }
+
+    fn upsert(&self, state: State) -> Result<(), Error> {
+        let mut states = self.states.write().unwrap();
+        states.insert(state.id, state);
+        self.flush(&states)
+    }
+
+    fn remove(&self, id: &Uuid) -> Result<bool, Error> {
+        let mut states = self.states.write().unwrap();
+        let removed = states.remove(id).is_some();
+
+        if removed {
+            self.flush(&states)?;
+        }
+
+        Ok(removed)
+    }
+
+    fn remove_matching(&self, predicate: &JsonPredicate) -> Result<usize, Error> {
+        let mut states = self.states.write().unwrap();
+        let count_before = states.len();
+        states.retain(|_, s| !state_matches(predicate, s));
+        let removed = count_before - states.len();
+
+        if removed > 0 {
+            self.flush(&states)?;
+        }
+
+        Ok(removed)
+    }
+}
+
+fn state_matches(predicate: &JsonPredicate, state: &State) -> bool {
+    match predicate.validate(state.data.clone()) {
+        Ok(res) => res,
+        Err(err) => {
+            error!("{err}");
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod state_storage_tests {
+    use crate::model::persistent::State;
+    use crate::predicate_dsl::json::JsonPredicate;
+    use crate::storage::state::{JsonFileStateStorage, StateStorage};
+    use crate::test_utils::temp_path;
+    use serde_json::json;
+
+    #[test]
+    fn json_file_storage_survives_reopening() {
+        let path = temp_path("states");
+
+        let storage = JsonFileStateStorage::open(path.clone()).unwrap();
+        storage.upsert(State { data: json!({"id": 1}), ..State::fresh() }).unwrap();
+        storage.upsert(State { data: json!({"id": 2}), ..State::fresh() }).unwrap();
+
+        let predicate: JsonPredicate = serde_json::from_value(json!({"id": {"==": 1}})).unwrap();
+        assert_eq!(storage.remove_matching(&predicate).unwrap(), 1);
+
+        let reopened = JsonFileStateStorage::open(path.clone()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let states = reopened.all();
+        assert_eq!(states.len(), 1);
+        assert_eq!(states[0].data, json!({"id": 2}));
+    }
+}
@@ -0,0 +1,142 @@
+use crate::error::Error;
+use crate::model::persistent::HttpStub;
+use crate::storage::write_atomically;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+/// Durable storage for stubs created at runtime, file-defined stubs never go there
+pub trait StubStorage: Send + Sync {
+    fn load(&self) -> Result<Vec<HttpStub>, Error>;
+    fn upsert(&self, stub: &HttpStub) -> Result<(), Error>;
+    fn remove(&self, id: &Uuid) -> Result<(), Error>;
+}
+
+/// Keeps nothing, runtime stubs are lost on restart
+pub struct VolatileStubStorage;
+
+impl StubStorage for VolatileStubStorage {
+    fn load(&self) -> Result<Vec<HttpStub>, Error> {
+        Ok(vec![])
+    }
+
+    fn upsert(&self, _stub: &HttpStub) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn remove(&self, _id: &Uuid) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum StubLogEntry {
+    Upsert { stub: Box<HttpStub> },
+    Delete { id: Uuid }
+}
+
+/// Records every change as a line of JSON, the log is compacted on startup
+pub struct AppendOnlyStubStorage {
+    stubs: Vec<HttpStub>,
+    log: Mutex<File>
+}
+
+impl AppendOnlyStubStorage {
+    pub fn open(path: PathBuf) -> Result<AppendOnlyStubStorage, Error> {
+        let mut stubs: Vec<HttpStub> = Vec::new();
+
+        if path.exists() {
+            let contents = fs::read_to_string(&path).map_err(Error::from)?;
+
+            for (line_no, line) in contents.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+                let entry = serde_json::from_str::<StubLogEntry>(line)
+                    .map_err(|e| Error::new(format!("Can't parse stub log {} at line {}: {}", path.display(), line_no + 1, e)))?;
+
+                match entry {
+                    StubLogEntry::Upsert { stub } => {
+                        stubs.retain(|s| s.id != stub.id);
+                        stubs.push(*stub);
+                    },
+                    StubLogEntry::Delete { id } => stubs.retain(|s| s.id != id)
+                }
+            }
+        }
+
+        let mut compacted = String::new();
+        for stub in stubs.iter() {
+            compacted.push_str(&to_line(&StubLogEntry::Upsert { stub: Box::new(stub.clone()) })?);
+        }
+
+        write_atomically(&path, compacted.as_bytes())?;
+
+        info!("Loaded {} runtime stub(s) from {}", stubs.len(), path.display());
+
+        let log = OpenOptions::new().append(true).open(&path).map_err(Error::from)?;
+
+        Ok(AppendOnlyStubStorage { stubs, log: Mutex::new(log) })
+    }
+
+    fn append(&self, entry: StubLogEntry) -> Result<(), Error> {
+        let line = to_line(&entry)?;
+        let mut log = self.log.lock().unwrap();
+
+        log.write_all(line.as_bytes()).and_then(|_| log.flush()).map_err(Error::from)
+    }
+}
+
+impl StubStorage for AppendOnlyStubStorage {
+    fn load(&self) -> Result<Vec<HttpStub>, Error> {
+        Ok(self.stubs.clone())
+    }
+
+    fn upsert(&self, stub: &HttpStub) -> Result<(), Error> {
+        self.append(StubLogEntry::Upsert { stub: Box::new(stub.clone()) })
+    }
+
+    fn remove(&self, id: &Uuid) -> Result<(), Error> {
+        self.append(StubLogEntry::Delete { id: *id })
+    }
+}
+
+fn to_line(entry: &StubLogEntry) -> Result<String, Error> {
+    serde_json::to_string(entry).map(|l| l + "\n").map_err(Error::from)
+}
+
+#[cfg(test)]
+mod stub_storage_tests {
+    use crate::model::persistent::HttpStub;
+    use crate::storage::stub::{AppendOnlyStubStorage, StubStorage};
+    use crate::test_utils::temp_path;
+    use serde_json::json;
+
+    fn stub(name: &str) -> HttpStub {
+        crate::test_utils::stub(name, json!({"id": uuid::Uuid::new_v4()}))
+    }
+
+    #[test]
+    fn replay_log_on_reopen() {
+        let path = temp_path("stubs");
+
+        let storage = AppendOnlyStubStorage::open(path.clone()).unwrap();
+        let (first, mut second, third) = (stub("first"), stub("second"), stub("third"));
+        storage.upsert(&first).unwrap();
+        storage.upsert(&second).unwrap();
+        storage.upsert(&third).unwrap();
+        second.name = "second, updated".to_string();
+        storage.upsert(&second).unwrap();
+        storage.remove(&first.id).unwrap();
+
+        let reopened = AppendOnlyStubStorage::open(path.clone()).unwrap();
+        let lines = std::fs::read_to_string(&path).unwrap().lines().count();
+        std::fs::remove_file(&path).unwrap();
+
+        let names = reopened.load().unwrap().into_iter().map(|s| s.name).collect::<Vec<_>>();
+        assert_eq!(names, vec!["third".to_string(), "second, updated".to_string()]);
+        assert_eq!(lines, 2);
+    }
+}
@@ -0,0 +1,77 @@
+use crate::api::resolver::StubResolver;
+use crate::model::persistent::HttpStub;
+use crate::storage::state::InMemoryStateStorage;
+use crate::storage::stub::VolatileStubStorage;
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+use actix_web::http::header::HeaderMap;
+use chrono::TimeDelta;
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokio::sync::RwLock;
+
+/// Request seen by a local listener
+pub struct Received {
+    pub path: String,
+    pub headers: HeaderMap,
+    pub body: String
+}
+
+/// Minimal stub answering GET `/{name}` with its name, fields of `overrides` replace the defaults
+pub fn stub_json(name: &str, overrides: Value) -> Value {
+    let mut stub = json!({
+        "name": name,
+        "scope": "persistent",
+        "method": "GET",
+        "path": format!("/{}", name),
+        "request": {"mode": "no_body", "headers": {}},
+        "response": {"mode": "raw", "code": 200, "headers": {}, "body": name}
+    });
+
+    if let (Some(fields), Value::Object(overrides)) = (stub.as_object_mut(), overrides) {
+        fields.extend(overrides);
+    }
+
+    stub
+}
+
+pub fn stub(name: &str, overrides: Value) -> HttpStub {
+    serde_json::from_value(stub_json(name, overrides)).unwrap()
+}
+
+/// Resolver keeping everything in memory
+pub fn resolver(stubs: Vec<HttpStub>) -> StubResolver {
+    StubResolver::new(RwLock::new(stubs), Box::new(InMemoryStateStorage::new()), Box::new(VolatileStubStorage), TimeDelta::seconds(60))
+}
+
+/// Unique path in the temporary directory, nothing is created there
+pub fn temp_path(prefix: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("kolibri-{}-{}", prefix, uuid::Uuid::new_v4()))
+}
+
+/// Starts a server on a random local port, it answers with `respond` and keeps every request it got
+pub fn listen(respond: fn(&HttpRequest) -> HttpResponse) -> (SocketAddr, Arc<Mutex<Vec<Received>>>) {
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let server_received = received.clone();
+
+    let server = HttpServer::new(move || {
+        let received = server_received.clone();
+
+        App::new().default_service(web::to(move |req: HttpRequest, body: web::Bytes| {
+            received.lock().unwrap().push(Received {
+                path: req.path().to_string(),
+                headers: req.headers().clone(),
+                body: String::from_utf8_lossy(&body).to_string()
+            });
+
+            let response = respond(&req);
+            async move { response }
+        }))
+    }).workers(1).bind(("127.0.0.1", 0)).unwrap();
+
+    let address = server.addrs()[0];
+    actix_web::rt::spawn(server.run());
+
+    (address, received)
+}
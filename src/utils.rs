@@ -2,6 +2,7 @@ use bigdecimal::BigDecimal;
 
 pub mod js;
 pub mod transformations;
+pub mod xml;
 
 pub trait IntoBD {
     fn to_big_decimal(self) -> BigDecimal;
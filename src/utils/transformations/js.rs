@@ -8,6 +8,7 @@ use crate::utils::js::optic::{JsonOptic, ValueExt};
 use crate::utils::transformations::CODE_PATTERN;
 
 static JSON_OPTIC_PATTERN: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\$([:~])?\{([\p{L}\d\.\[\]\-_]+)\}").unwrap());
+static TEMPLATE_PATTERN: LazyLock<Regex> = LazyLock::new(|| Regex::new(&format!("{}|{}", JSON_OPTIC_PATTERN.as_str(), CODE_PATTERN.as_str())).unwrap());
 
 pub struct JsonPatcher {
     new_value: Value
@@ -73,12 +74,65 @@ impl JsonTemplater {
 
             if let [cap] = &code_captures[..] {
                 let code = &cap[1];
-                return Some(JsonPatcher::new(self.code_runner.eval(code).unwrap()));
+                return self.code_runner.eval(code).ok().map(JsonPatcher::new);
             }
         }
 
         None
     }
+
+    /// Substitutes templated strings anywhere within the value
+    pub fn substitute_in_place(&mut self, target: &mut Value) {
+        let mut upd = |vx: &mut Value| {
+            match &vx {
+                Value::String(s) => {
+                    if let Some(patcher) = self.make_patcher_fn(&s) {
+                        patcher.apply(vx)
+                    }
+                },
+                _ => ()
+            }
+        };
+
+        target.update_in_place_by_closure_mut(&mut upd);
+    }
+
+    /// Tells whether the string holds any `${...}` or `%{...}` placeholder
+    pub fn is_template(defn: &str) -> bool {
+        TEMPLATE_PATTERN.is_match(defn)
+    }
+
+    /// Tells whether any string inside the value holds a placeholder
+    pub fn has_templates(value: &Value) -> bool {
+        match value {
+            Value::String(s) => JsonTemplater::is_template(s),
+            Value::Array(vs) => vs.iter().any(JsonTemplater::has_templates),
+            Value::Object(kvs) => kvs.values().any(JsonTemplater::has_templates),
+            _ => false
+        }
+    }
+
+    /// Replaces every `${...}` and `%{...}` occurrence in the string with its rendered value
+    pub fn interpolate(&mut self, defn: &str) -> String {
+        self.interpolate_escaped(defn, |s| s.to_string())
+    }
+
+    /// Same as `interpolate`, but rendered values are passed through `escape` before insertion.
+    /// Both kinds of placeholders are resolved in a single pass, so substituted values are never evaluated
+    pub fn interpolate_escaped(&mut self, defn: &str, escape: fn(&str) -> String) -> String {
+        let values = &self.values;
+        let code_runner = &mut self.code_runner;
+
+        TEMPLATE_PATTERN.replace_all(defn, |caps: &Captures| {
+            let rendered = match (caps.get(2), caps.get(3)) {
+                (Some(path), _) => values.get_all(&JsonOptic::from_path(path.as_str())).first().map(|v| render_subst(v)),
+                (_, Some(code)) => code_runner.eval(code.as_str()).ok().map(|v| render_subst(&v)),
+                _ => None
+            };
+
+            rendered.map(|r| escape(&r)).unwrap_or(caps[0].to_string())
+        }).to_string()
+    }
 }
 
 pub trait JsonTransformations {
@@ -115,20 +169,7 @@ impl JsonTransformations for Value {
     }
 
     fn substitute_in_place(&mut self, values: Value) {
-        let mut templater = JsonTemplater::new(values);
-
-        let mut upd = |vx: &mut Value| {
-            match &vx {
-                Value::String(s) => {
-                    if let Some(patcher) = templater.make_patcher_fn(&s) {
-                        patcher.apply(vx)
-                    }
-                },
-                _ => ()
-            }
-        };
-
-        self.update_in_place_by_closure_mut(&mut upd);
+        JsonTemplater::new(values).substitute_in_place(self);
     }
 
     fn patch_in_place(&mut self, values: Value, schema: HashMap<JsonOptic, String>) {
@@ -180,6 +221,8 @@ mod json_templater_tests {
     use fluent_assertions::*;
     use serde_json::{json, Value};
     use uuid::Uuid;
+    use crate::misc::Substitute;
+    use crate::model::persistent::{HttpStubResponse, ResponseCode};
     use crate::utils::transformations::js::*;
 
     #[test]
@@ -380,4 +423,103 @@ mod json_templater_tests {
         assert_eq!(target.get_all(&JsonOptic::from_path("a2.[4]")), vec![&Value::String("nondesc".to_string())]);
         assert_eq!(target.get_all(&JsonOptic::from_path("o3.client")), vec![&Value::String("Peka Kekovsky".to_string())]);
     }
+
+    #[test]
+    fn interpolate_string() {
+        let data: Value = json!(
+            {
+                "id": 42,
+                "user": {"name": "Peka"}
+            }
+        );
+
+        let mut templater = JsonTemplater::new(data);
+
+        assert_eq!(templater.interpolate("/items/${id}?owner=${user.name}"), "/items/42?owner=Peka");
+        assert_eq!(templater.interpolate("${missing}"), "${missing}");
+        assert_eq!(templater.interpolate("sum: %{1 + 2}"), "sum: 3");
+    }
+
+    #[test]
+    fn substituted_values_are_not_evaluated() {
+        let data: Value = json!({"name": "%{1+1}", "broken": "%{(}"});
+
+        let mut templater = JsonTemplater::new(data);
+
+        assert_eq!(templater.interpolate("${name} = %{1+1}"), "%{1+1} = 2");
+        assert_eq!(templater.interpolate("${broken}"), "%{(}");
+        assert_eq!(templater.interpolate("left as is: %{(}"), "left as is: %{(}");
+    }
+
+    #[test]
+    fn templated_response_headers_and_code() {
+        let mut response = serde_json::from_value::<HttpStubResponse>(json!({
+            "mode": "raw",
+            "code": "${req.code}",
+            "headers": {"Location": "/items/${pathParts.id}", "X-Sum": "%{1 + 2}"},
+            "body": "created ${pathParts.id}",
+            "template": true
+        })).unwrap();
+
+        response.substitute(json!({"req": {"code": 201}, "pathParts": {"id": 42}}));
+
+        assert_eq!(response.get_code(), 201);
+        assert!(matches!(response, HttpStubResponse::RawResponse { ref headers, ref body, .. }
+            if headers["Location"] == "/items/42" && headers["X-Sum"] == "3" && body == "created 42"));
+    }
+
+    #[test]
+    fn xml_response_values_are_escaped() {
+        let mut response = serde_json::from_value::<HttpStubResponse>(json!({
+            "mode": "xml",
+            "code": 200,
+            "headers": {},
+            "body": "<user name=\"${req.name}\">%{req.note + ' & more'}</user>"
+        })).unwrap();
+
+        response.substitute(json!({"req": {"name": "Tom \"<Cat>\"", "note": "a<b"}}));
+
+        assert!(matches!(response, HttpStubResponse::XmlResponse { ref body, .. }
+            if body == "<user name=\"Tom &quot;&lt;Cat&gt;&quot;\">a&lt;b &amp; more</user>"));
+    }
+
+    #[test]
+    fn raw_body_is_verbatim_without_template_flag() {
+        let mut response = serde_json::from_value::<HttpStubResponse>(json!({
+            "mode": "raw",
+            "code": 200,
+            "headers": {},
+            "body": "printf('%{d}', ${id})"
+        })).unwrap();
+
+        response.substitute(json!({"id": 42}));
+
+        assert!(matches!(response, HttpStubResponse::RawResponse { ref body, .. } if body == "printf('%{d}', ${id})"));
+    }
+
+    #[test]
+    fn code_template_falls_back_to_500() {
+        let mut response = serde_json::from_value::<HttpStubResponse>(json!({
+            "mode": "json",
+            "code": "${req.code}",
+            "headers": {},
+            "body": {}
+        })).unwrap();
+
+        response.substitute(json!({"req": {"code": "teapot"}}));
+
+        assert_eq!(response.get_code(), 500);
+        assert!(matches!(response, HttpStubResponse::JsonResponse { code: ResponseCode::Template(_), .. }));
+    }
+
+    #[test]
+    fn placeholders_are_detected() {
+        assert!(JsonTemplater::is_template("${a.b}"));
+        assert!(JsonTemplater::is_template("$:{a}"));
+        assert!(JsonTemplater::is_template("x %{1 + 1} y"));
+        assert!(!JsonTemplater::is_template("plain $ text {}"));
+
+        assert!(JsonTemplater::has_templates(&json!({"a": [1, {"b": "${c}"}]})));
+        assert!(!JsonTemplater::has_templates(&json!({"a": [1, {"b": "c"}]})));
+    }
 }
\ No newline at end of file
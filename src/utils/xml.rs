@@ -0,0 +1,173 @@
+use crate::error::Error;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use sxd_document::Package;
+use sxd_document::dom::{ChildOfElement, ChildOfRoot, Element};
+use sxd_document::parser;
+
+pub fn parse(xml: &str) -> Result<Package, Error> {
+    parser::parse(xml).map_err(|e| Error::new(format!("Malformed XML: {}", e)))
+}
+
+/// Compares documents ignoring formatting whitespace, comments, attribute order and namespace prefixes
+pub fn same_document(one: &str, other: &str) -> bool {
+    match (parse(one), parse(other)) {
+        (Ok(one), Ok(other)) => root_of(&one).map(Node::of) == root_of(&other).map(Node::of),
+        _ => false
+    }
+}
+
+/// Converts a document into JSON: `<order id="1"><item>a</item><item>b</item></order>`
+/// becomes `{"order": {"@id": 1, "item": ["a", "b"]}}`
+pub fn to_json(package: &Package) -> Value {
+    match root_of(package) {
+        Some(root) => Value::Object(Map::from_iter([(root.name().local_part().to_string(), element_to_json(root))])),
+        None => Value::Null
+    }
+}
+
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Text from documents is typed the same way query parameters are
+pub fn typed(text: &str) -> Value {
+    serde_json::from_str(text).unwrap_or(Value::String(text.to_string()))
+}
+
+// ---- private stuff ----
+
+#[derive(PartialEq)]
+enum Node {
+    Element {
+        name: (Option<String>, String),
+        attributes: BTreeMap<(Option<String>, String), String>,
+        children: Vec<Node>
+    },
+    Text(String)
+}
+
+impl Node {
+    fn of(element: Element) -> Node {
+        let mut children = Vec::new();
+        let mut text = String::new();
+
+        for child in element.children() {
+            match child {
+                ChildOfElement::Element(el) => {
+                    push_text(&mut children, &mut text);
+                    children.push(Node::of(el));
+                },
+                ChildOfElement::Text(t) => text.push_str(t.text()),
+                _ => ()
+            }
+        }
+        push_text(&mut children, &mut text);
+
+        Node::Element {
+            name: (element.name().namespace_uri().map(String::from), element.name().local_part().to_string()),
+            attributes: element.attributes().into_iter()
+                .map(|attr| ((attr.name().namespace_uri().map(String::from), attr.name().local_part().to_string()), attr.value().to_string()))
+                .collect(),
+            children
+        }
+    }
+}
+
+fn push_text(children: &mut Vec<Node>, text: &mut String) {
+    let trimmed = text.trim();
+
+    if !trimmed.is_empty() {
+        children.push(Node::Text(trimmed.to_string()));
+    }
+
+    text.clear();
+}
+
+fn root_of(package: &Package) -> Option<Element<'_>> {
+    package.as_document().root().children().into_iter().find_map(|child| match child {
+        ChildOfRoot::Element(el) => Some(el),
+        _ => None
+    })
+}
+
+fn element_to_json(element: Element) -> Value {
+    let mut fields = Map::new();
+    let mut text = String::new();
+
+    for attr in element.attributes() {
+        fields.insert(format!("@{}", attr.name().local_part()), typed(attr.value()));
+    }
+
+    for child in element.children() {
+        match child {
+            ChildOfElement::Element(el) => {
+                let name = el.name().local_part().to_string();
+                let value = element_to_json(el);
+
+                match fields.get_mut(&name) {
+                    Some(Value::Array(values)) => values.push(value),
+                    Some(existing) => *existing = Value::Array(vec![existing.take(), value]),
+                    None => drop(fields.insert(name, value))
+                }
+            },
+            ChildOfElement::Text(t) => text.push_str(t.text()),
+            _ => ()
+        }
+    }
+
+    let text = text.trim();
+
+    match (fields.is_empty(), text.is_empty()) {
+        (true, true) => Value::Null,
+        (true, false) => typed(text),
+        (false, true) => Value::Object(fields),
+        (false, false) => {
+            fields.insert("#text".to_string(), typed(text));
+            Value::Object(fields)
+        }
+    }
+}
+
+#[cfg(test)]
+mod xml_tests {
+    use crate::utils::xml::{escape, parse, same_document, to_json};
+    use serde_json::json;
+
+    #[test]
+    fn documents_are_compared_after_normalization() {
+        let one = r#"<?xml version="1.0"?>
+            <ns:order xmlns:ns="urn:orders" b="2" a="1">
+                <!-- comment -->
+                <ns:item>apple</ns:item>
+            </ns:order>"#;
+        let other = r#"<o:order xmlns:o="urn:orders" a="1" b="2"><o:item>apple</o:item></o:order>"#;
+
+        assert!(same_document(one, other));
+        assert!(!same_document(one, r#"<order a="1" b="2"><item>apple</item></order>"#));
+        assert!(!same_document(one, r#"<o:order xmlns:o="urn:orders" a="1" b="2"><o:item>pear</o:item></o:order>"#));
+    }
+
+    #[test]
+    fn document_converts_to_json() {
+        let package = parse(r#"<order id="42"><item>apple</item><item>pear</item><note lang="en">fragile</note><empty/></order>"#).unwrap();
+
+        assert_eq!(to_json(&package), json!({
+            "order": {
+                "@id": 42,
+                "item": ["apple", "pear"],
+                "note": {"@lang": "en", "#text": "fragile"},
+                "empty": null
+            }
+        }));
+    }
+
+    #[test]
+    fn markup_is_escaped() {
+        assert_eq!(escape(r#"<a href="x">Tom & 'Jerry'</a>"#), "&lt;a href=&quot;x&quot;&gt;Tom &amp; &apos;Jerry&apos;&lt;/a&gt;");
+    }
+}